@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+// Cargo doesn't let aliases shadow its own subcommands.
+const CARGO_SUBCOMMANDS: &[&str] = &[
+    "add",
+    "bench",
+    "build",
+    "check",
+    "clean",
+    "cmd",
+    "config",
+    "doc",
+    "fetch",
+    "fix",
+    "generate-lockfile",
+    "help",
+    "info",
+    "init",
+    "install",
+    "locate-project",
+    "login",
+    "logout",
+    "metadata",
+    "new",
+    "owner",
+    "package",
+    "pkgid",
+    "publish",
+    "remove",
+    "report",
+    "run",
+    "rustc",
+    "rustdoc",
+    "search",
+    "test",
+    "tree",
+    "uninstall",
+    "update",
+    "vendor",
+    "verify-project",
+    "version",
+    "yank",
+];
+
+fn config_path() -> PathBuf {
+    // Cargo still reads the config without an extension if that's what the
+    // project already has.
+    let legacy = Path::new(".cargo/config");
+    if legacy.is_file() && !Path::new(".cargo/config.toml").exists() {
+        legacy.to_path_buf()
+    } else {
+        PathBuf::from(".cargo/config.toml")
+    }
+}
+
+fn read_config(path: &Path) -> Result<DocumentMut, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(DocumentMut::new()),
+    };
+    contents
+        .parse()
+        .or(Err(format!("Could not parse {}", path.display())))
+}
+
+fn write_config(path: &Path, config: &DocumentMut) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).or(Err(format!("Could not create {}", dir.display())))?;
+    }
+    fs::write(path, config.to_string()).or(Err(format!("Could not write {}", path.display())))
+}
+
+fn alias_for(name: &str) -> String {
+    format!("cmd {}", name)
+}
+
+// Add a `cargo <name>` alias for each command that can have one, leaving
+// aliases that are already defined alone.
+pub fn install<T>(commands: &HashMap<String, T>) -> Result<(), String> {
+    let path = config_path();
+    let mut config = read_config(&path)?;
+    let aliases = config
+        .entry("alias")
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+        .ok_or(format!("[alias] in {} is not a table", path.display()))?;
+
+    let mut names: Vec<&String> = commands.keys().filter(|name| !name.contains('*')).collect();
+    names.sort();
+
+    for name in names {
+        if CARGO_SUBCOMMANDS.contains(&&name[..]) {
+            println!("Skipped `{}`, cargo already has a `{}` command", name, name);
+            continue;
+        }
+        match aliases.get(name).and_then(Item::as_str) {
+            Some(existing) if existing == alias_for(name) => {}
+            Some(existing) => println!(
+                "Skipped `{}`, it's already an alias for `cargo {}`",
+                name, existing
+            ),
+            None if aliases.contains_key(name) => {
+                println!("Skipped `{}`, it's already an alias", name)
+            }
+            None => {
+                aliases.insert(name, Item::Value(Value::from(alias_for(name))));
+                println!("Added `cargo {}`", name);
+            }
+        }
+    }
+
+    write_config(&path, &config)
+}
+
+// Remove every alias that `install` could have added.
+pub fn remove() -> Result<(), String> {
+    let path = config_path();
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut config = read_config(&path)?;
+    let aliases = match config.get_mut("alias").and_then(Item::as_table_mut) {
+        Some(aliases) => aliases,
+        None => return Ok(()),
+    };
+
+    let ours: Vec<String> = aliases
+        .iter()
+        .filter(|(name, alias)| alias.as_str() == Some(&alias_for(name)[..]))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    for name in &ours {
+        aliases.remove(name);
+        println!("Removed `cargo {}`", name);
+    }
+    if aliases.is_empty() {
+        config.remove("alias");
+    }
+
+    write_config(&path, &config)
+}
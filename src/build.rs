@@ -0,0 +1,83 @@
+// Builds that have to succeed before commands with `needs_build` or `bin`
+// run, for commands that run what the build produces. Each build is run once,
+// before any of the commands start. The path of a command's `bin` is passed to
+// it in CARGO_CMD_BIN.
+
+use command::{Command, NeedsBuild};
+use exec;
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use subprocess::{Exec, ExitStatus, Redirection};
+use term;
+
+// The arguments to build with for the command, using the --profile passed to
+// cargo-cmd unless it has one of its own.
+fn build_args(command: &Command, profile: Option<&str>) -> Option<Vec<String>> {
+    let options = &command.options;
+    let mut args = vec!["build".to_string()];
+    let (profile, target) = match options.needs_build {
+        None | Some(NeedsBuild::Always(false)) if options.bin.is_none() => return None,
+        Some(NeedsBuild::With {
+            profile: ref own,
+            ref target,
+        }) => (own.as_deref().or(profile), target.as_deref()),
+        _ => (profile, None),
+    };
+    for (flag, value) in [("--profile", profile), ("--target", target)] {
+        if let Some(value) = value {
+            args.extend([flag.to_string(), value.to_string()]);
+        }
+    }
+    if let Some(ref bin) = options.bin {
+        args.extend(["--bin".to_string(), bin.to_string()]);
+        args.push("--message-format=json-render-diagnostics".to_string());
+    }
+    Some(args)
+}
+
+// The executable cargo reports building for `bin` in its JSON messages.
+fn executable(messages: &str, bin: &str) -> Option<String> {
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter(|message| message["target"]["name"] == bin)
+        .find_map(|message| message["executable"].as_str().map(str::to_string))
+}
+
+pub fn ensure<'a, I>(
+    commands: I,
+    env: &[(String, String)],
+    profile: Option<&str>,
+) -> Result<(), String>
+where
+    I: IntoIterator<Item = &'a mut Command>,
+{
+    let mut built: HashMap<Vec<String>, String> = HashMap::new();
+    for command in commands {
+        let args = match build_args(command, profile) {
+            Some(args) => args,
+            None => continue,
+        };
+        if !built.contains_key(&args) {
+            term::status(&format!("> cargo {}", args.join(" ")));
+            let capture = Exec::cmd(exec::cargo())
+                .args(&args)
+                .env_extend(env)
+                .stdout(Redirection::Pipe)
+                .capture();
+            match capture {
+                Ok(capture) if capture.exit_status == ExitStatus::Exited(0) => {
+                    built.insert(args.clone(), capture.stdout_str());
+                }
+                _ => return Err(format!("`cargo {}` failed", args.join(" "))),
+            }
+        }
+        if let Some(ref bin) = command.options.bin {
+            let path = executable(&built[&args], bin)
+                .ok_or(format!("Could not find the executable of \"{}\"", bin))?;
+            command.options.bin_path = Some(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const PREFIX: &str = "cargo-cmd:";
+
+pub fn is_builtin(command: &str) -> bool {
+    command.starts_with(PREFIX)
+}
+
+// Rewrite every `cargo-cmd:<name>` word in a command string so the shell calls
+// back into this binary, which lets the builtins be used alongside pipes and
+// `&&` chains. The `--` stops clap from eating flags meant for the builtin.
+pub fn expand(command: &str) -> String {
+    if !command.contains(PREFIX) {
+        return command.to_string();
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe.to_string_lossy().into_owned(),
+        Err(_) => return command.to_string(),
+    };
+    let mut expanded = String::new();
+    let mut rest = command;
+
+    while let Some(start) = rest.find(PREFIX) {
+        let at_word_start = rest[..start]
+            .chars()
+            .last()
+            .is_none_or(|c| c.is_whitespace() || ";&|(".contains(c));
+        let name_len = rest[start..]
+            .find(|c: char| c.is_whitespace() || ";&|)".contains(c))
+            .unwrap_or(rest.len() - start);
+
+        expanded.push_str(&rest[..start]);
+        if at_word_start {
+            expanded.push_str(&format!(
+                "\"{}\" cmd {} --",
+                exe,
+                &rest[start..start + name_len]
+            ));
+        } else {
+            expanded.push_str(&rest[start..start + name_len]);
+        }
+        rest = &rest[start + name_len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+pub fn run(command: &str, args: &[String]) -> Result<(), String> {
+    let name = &command[PREFIX.len()..];
+    let (flags, paths): (Vec<&String>, Vec<&String>) = args
+        .iter()
+        .partition(|arg| arg.starts_with('-') && arg.len() > 1);
+    let has_flag = |flag: char| flags.iter().any(|f| f[1..].contains(flag));
+    let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+    let fail =
+        |path: &Path, error: io::Error| format!("{}: {}: {}", command, path.display(), error);
+
+    // Like in a shell, `rm -f` without operands does nothing.
+    if paths.is_empty() && name == "rm" && has_flag('f') {
+        return Ok(());
+    }
+    if paths.is_empty() {
+        return Err(format!("{}: missing operand", command));
+    }
+
+    match name {
+        "rm" => {
+            for path in paths {
+                let result = if path.is_dir() && !has_flag('r') {
+                    Err(io::Error::other("is a directory"))
+                } else if path.is_dir() {
+                    fs::remove_dir_all(path)
+                } else {
+                    fs::remove_file(path)
+                };
+                match result {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound && has_flag('f') => {}
+                    Err(e) => return Err(fail(path, e)),
+                    Ok(_) => {}
+                }
+            }
+        }
+        "mkdir" => {
+            for path in paths {
+                let result = if has_flag('p') {
+                    fs::create_dir_all(path)
+                } else {
+                    fs::create_dir(path)
+                };
+                result.map_err(|e| fail(path, e))?;
+            }
+        }
+        "touch" => {
+            for path in paths {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| fail(path, e))?;
+            }
+        }
+        "cp" | "mv" => {
+            let (dest, sources) = paths.split_last().unwrap();
+            if sources.is_empty() {
+                return Err(format!("{}: missing destination operand", command));
+            }
+            for source in sources {
+                let target = if dest.is_dir() {
+                    dest.join(source.file_name().unwrap_or_default())
+                } else {
+                    dest.to_path_buf()
+                };
+                let result = if name == "mv" {
+                    fs::rename(source, &target)
+                } else if source.is_dir() && !has_flag('r') {
+                    Err(io::Error::other("is a directory"))
+                } else {
+                    copy(source, &target)
+                };
+                result.map_err(|e| fail(source, e))?;
+            }
+        }
+        _ => return Err(format!("Unknown builtin command \"{}\"", command)),
+    }
+
+    Ok(())
+}
+
+fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    if !source.is_dir() {
+        return fs::copy(source, target).map(|_| ());
+    }
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        copy(&entry.path(), &target.join(entry.file_name()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+// Parsed commands are kept under target/ along with the modification times of
+// every file (and globbed directory) they came from, so later runs can skip
+// reading and globbing as long as none of those have changed.
+
+use command::Command;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use toml;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Cache {
+    version: String,
+    strict: bool,
+    sources: Vec<Source>,
+    commands: HashMap<String, Command>,
+    // The commands that are steps of a list, which the commands don't keep
+    // themselves.
+    steps: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Source {
+    path: PathBuf,
+    modified: i64,
+    size: i64,
+}
+
+impl Source {
+    // Missing files are recorded too, so creating one invalidates the cache.
+    // The size catches changes on file systems with coarse timestamps.
+    pub fn new(path: &Path) -> Source {
+        let metadata = fs::metadata(path).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(-1, |modified| modified.as_nanos() as i64);
+        Source {
+            path: path.to_path_buf(),
+            modified,
+            size: metadata.map_or(-1, |metadata| metadata.len() as i64),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        *self == Source::new(&self.path)
+    }
+}
+
+fn path(dir: &Path) -> PathBuf {
+    dir.join("target/cargo-cmd/cache/commands.toml")
+}
+
+pub fn load(dir: &Path, strict: bool) -> Option<HashMap<String, Command>> {
+    let cache: Cache = toml::from_str(&fs::read_to_string(path(dir)).ok()?).ok()?;
+    if cache.version != VERSION
+        || cache.strict != strict
+        || !cache.sources.iter().all(Source::is_fresh)
+    {
+        return None;
+    }
+    let mut commands = cache.commands;
+    for step in &cache.steps {
+        if let Some(command) = commands.get_mut(step) {
+            command.options.step = true;
+        }
+    }
+    Some(commands)
+}
+
+// The cache is only an optimization, so failing to write it is ignored.
+pub fn store(dir: &Path, strict: bool, sources: Vec<Source>, commands: &HashMap<String, Command>) {
+    let cache = Cache {
+        version: VERSION.to_string(),
+        strict,
+        sources,
+        commands: commands.clone(),
+        steps: commands
+            .iter()
+            .filter(|(_, command)| command.options.step)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    };
+    let contents = match toml::Value::try_from(&cache).map(|value| value.to_string()) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+    let path = path(dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    fs::write(path, contents).ok();
+}
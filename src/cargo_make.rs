@@ -0,0 +1,134 @@
+use quote;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+
+#[derive(Deserialize, Debug)]
+struct Task {
+    command: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    script: Option<Script>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Script {
+    Line(String),
+    Lines(Vec<String>),
+}
+
+// Keys of cargo-make tasks that change when or how they run, which a shell
+// command can't express.
+const UNSUPPORTED_KEYS: &[&str] = &[
+    "condition",
+    "condition_script",
+    "script_runner",
+    "linux",
+    "mac",
+    "windows",
+    "cwd",
+    "env",
+    "run_task",
+    "install_crate",
+];
+
+// Map the simple tasks of a cargo-make `Makefile.toml` onto shell commands.
+// Tasks that rely on cargo-make specific features (custom script runners,
+// conditions, platform overrides, script files, ...) are left out, along with
+// the tasks that depend on them, since there is nothing sensible to map them
+// to. A Makefile.toml that can't be read only gets a warning, so it never
+// gets in the way of the commands in Cargo.toml.
+pub fn load(path: &Path) -> HashMap<String, String> {
+    let mut commands = HashMap::new();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return commands,
+    };
+    let makefile: Value = match toml::from_str(&contents) {
+        Ok(makefile) => makefile,
+        Err(_) => {
+            eprintln!("warning: Could not parse Makefile.toml, its tasks are left out");
+            return commands;
+        }
+    };
+    let tasks: HashMap<String, Task> = match makefile.get("tasks").and_then(Value::as_table) {
+        Some(tasks) => tasks
+            .iter()
+            .filter(|(_, task)| {
+                task.as_table()
+                    .is_some_and(|task| !UNSUPPORTED_KEYS.iter().any(|key| task.contains_key(*key)))
+            })
+            .filter_map(|(name, task)| Some((name.to_string(), task.clone().try_into().ok()?)))
+            .collect(),
+        None => return commands,
+    };
+
+    for name in tasks.keys() {
+        let mut steps = vec![];
+        if expand_task(name, &tasks, &mut vec![], &mut vec![], &mut steps) && !steps.is_empty() {
+            commands.insert(name.to_string(), steps.join(" && "));
+        }
+    }
+
+    commands
+}
+
+// Dependencies are inlined ahead of the task's own steps rather than run
+// through `cargo cmd`, which may not be installed where the task is run. Like
+// with cargo-make, a dependency that several tasks share runs once. Returns
+// false if the task, or one of its dependencies, can't be mapped.
+fn expand_task(
+    name: &str,
+    tasks: &HashMap<String, Task>,
+    seen: &mut Vec<String>,
+    expanded: &mut Vec<String>,
+    steps: &mut Vec<String>,
+) -> bool {
+    let task = match tasks.get(name) {
+        Some(task) => task,
+        None => return false,
+    };
+    if task.disabled || expanded.iter().any(|e| e == name) {
+        return true;
+    }
+    if seen.iter().any(|s| s == name) {
+        eprintln!(
+            "warning: Task \"{}\" in Makefile.toml depends on itself, it is left out",
+            name
+        );
+        return false;
+    }
+    seen.push(name.to_string());
+
+    for dependency in &task.dependencies {
+        if !expand_task(dependency, tasks, seen, expanded, steps) {
+            return false;
+        }
+    }
+
+    match (&task.command, &task.script) {
+        (Some(command), _) => {
+            let mut line = vec![quote::quote(command)];
+            line.extend(task.args.iter().map(|arg| quote::quote(arg)));
+            steps.push(line.join(" "));
+        }
+        // A script of several lines runs as a whole in a subshell, so it's
+        // still skipped when a step before it fails.
+        (None, Some(Script::Line(script))) if script.trim().contains('\n') => {
+            steps.push(format!("(\n{}\n)", script.trim()))
+        }
+        (None, Some(Script::Line(script))) => steps.push(script.trim().to_string()),
+        (None, Some(Script::Lines(lines))) => steps.extend(lines.iter().cloned()),
+        (None, None) => {}
+    }
+
+    seen.pop();
+    expanded.push(name.to_string());
+    true
+}
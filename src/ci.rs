@@ -0,0 +1,111 @@
+// CI mode, from --ci or the CI variable most CI services set. Nobody is
+// there to answer prompts or look at colors, output of parallel runs is kept
+// apart, and a JSON summary of the run is printed to stderr at the end, so it
+// doesn't end up in the output of commands that are piped or redirected.
+
+use command::Command;
+use journal::Timing;
+use serde_json;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+        || env::var("CI").is_ok_and(|ci| !["", "0", "false"].contains(&&ci[..]))
+}
+
+// Whether someone is there to answer questions.
+pub fn interactive() -> bool {
+    !enabled() && io::stdin().is_terminal()
+}
+
+// Variables that turn colors off in the commands, unless they were asked for.
+pub fn env() -> Vec<(String, String)> {
+    if ["FORCE_COLOR", "CARGO_TERM_COLOR"]
+        .iter()
+        .any(|name| env::var_os(name).is_some())
+    {
+        return vec![];
+    }
+    vec![
+        ("NO_COLOR".to_string(), "1".to_string()),
+        ("CARGO_TERM_COLOR".to_string(), "never".to_string()),
+    ]
+}
+
+// The file the output of a parallel run is kept in until the runs are done.
+fn output_path(run: usize) -> PathBuf {
+    env::temp_dir().join(format!("cargo-cmd-output-{}-{}", process::id(), run))
+}
+
+// The command with its output kept for `print_output`, unless it already goes
+// to a file or is only shown if the command fails.
+pub fn buffer(run: usize, command: &Command) -> Command {
+    let mut command = command.clone();
+    let options = &mut command.options;
+    if options.stdout_file.is_none() && options.stderr_file.is_none() && !options.quiet {
+        let path = output_path(run).to_string_lossy().into_owned();
+        options.stdout_file = Some(path.clone());
+        options.stderr_file = Some(path);
+        options.append = false;
+    }
+    command
+}
+
+pub fn print_output(run: usize) {
+    let path = output_path(run);
+    if let Ok(output) = fs::read_to_string(&path) {
+        print!("{}", output);
+        fs::remove_file(path).ok();
+    }
+}
+
+// The summary printed to stderr at the end of a run in CI mode. `schema` goes
+// up when a field is removed or changes meaning, not when one is added.
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    pub schema: u32,
+    pub package: String,
+    pub command: String,
+    pub args: Vec<String>,
+    // In seconds.
+    pub duration: f64,
+    pub status: i32,
+    // How long each command in the chain took and what it used.
+    pub commands: Vec<Timing>,
+}
+
+pub const SCHEMA: u32 = 1;
+
+pub fn summary(
+    package: &str,
+    command: &str,
+    args: &[String],
+    duration: Duration,
+    status: i32,
+    commands: Vec<Timing>,
+) {
+    let summary = Summary {
+        schema: SCHEMA,
+        package: package.to_string(),
+        command: command.to_string(),
+        args: args.to_vec(),
+        duration: duration.as_secs_f64(),
+        status,
+        commands,
+    };
+    if let Ok(summary) = serde_json::to_string(&summary) {
+        eprintln!("{}", summary);
+    }
+}
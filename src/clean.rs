@@ -0,0 +1,50 @@
+// --clean removes what cargo-cmd keeps under target/cargo-cmd between runs,
+// or only the parts of it that are given.
+
+use jobs;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PARTS: &[&str] = &["fingerprints", "jobs", "journal", "logs", "mutexes"];
+
+fn remove(path: PathBuf, removed: &mut Vec<PathBuf>) -> Result<(), String> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(&path)
+    } else if path.exists() {
+        fs::remove_file(&path)
+    } else {
+        return Ok(());
+    };
+    result.or(Err(format!("Could not remove {}", path.display())))?;
+    removed.push(path);
+    Ok(())
+}
+
+pub fn clean(parts: &[String]) -> Result<(), String> {
+    let root = Path::new("target/cargo-cmd");
+    let wants = |part: &str| parts.is_empty() || parts.iter().any(|given| given == part);
+    let mut removed = vec![];
+    // The cache of the commands read from the manifests, which is kept as
+    // long as the fingerprints of the manifests it was read from match.
+    if wants("fingerprints") {
+        remove(root.join("cache"), &mut removed)?;
+    }
+    if wants("jobs") || wants("logs") {
+        removed.extend(jobs::clean(wants("jobs"), wants("logs")));
+    }
+    if wants("journal") {
+        remove(root.join("journal.jsonl"), &mut removed)?;
+    }
+    if wants("mutexes") {
+        remove(root.join("mutexes"), &mut removed)?;
+    }
+    fs::remove_dir(root).ok();
+
+    if removed.is_empty() {
+        println!("Nothing to clean in {}", root.display());
+    }
+    for path in removed {
+        println!("Removed {}", path.display());
+    }
+    Ok(())
+}
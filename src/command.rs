@@ -0,0 +1,418 @@
+use duration;
+use params::Param;
+use process_tree::Limits;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use size;
+use std::collections::{BTreeMap, HashMap};
+use toml;
+
+// A command can either be a plain shell string or a table that adds options
+// on top of the shell string in `cmd`, or runs the Rhai script in `script`
+// instead, which is then what `cmd` holds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "RawCommand", into = "RawCommand")]
+pub struct Command {
+    pub cmd: String,
+    pub options: Options,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Options {
+    pub description: Option<String>,
+    // The directory the command runs in.
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub params: Vec<Param>,
+    pub env_file: Option<String>,
+    pub nice: Option<i32>,
+    pub priority: Option<Priority>,
+    pub max_memory: Option<String>,
+    pub max_cpu_time: Option<String>,
+    pub wait_for: Option<WaitFor>,
+    pub needs_build: Option<NeedsBuild>,
+    // A binary of the package to build, with its path passed in CARGO_CMD_BIN.
+    pub bin: Option<String>,
+    // Where the build put `bin`, see `build::ensure`.
+    #[serde(skip)]
+    pub bin_path: Option<String>,
+    pub stdin: Option<String>,
+    pub stdout_file: Option<String>,
+    pub stderr_file: Option<String>,
+    #[serde(default)]
+    pub append: bool,
+    pub env_clear: Option<bool>,
+    pub env_allow: Option<Vec<String>>,
+    pub env_deny: Option<Vec<String>>,
+    // Replacements for the command when running for one of these targets.
+    #[serde(default)]
+    pub target: HashMap<String, Command>,
+    #[serde(default)]
+    pub matrix: BTreeMap<String, Vec<String>>,
+    pub parallel: Option<bool>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub path_prepend: Vec<String>,
+    // Cargo features the command needs, passed to it in `${features}`.
+    #[serde(default)]
+    pub required_features: Vec<String>,
+    // The rustup toolchain cargo and rustc run with, in RUSTUP_TOOLCHAIN.
+    pub toolchain: Option<String>,
+    // Exit codes that count as success, only 0 when empty.
+    #[serde(default)]
+    pub success_codes: Vec<i32>,
+    #[serde(default)]
+    pub expect_failure: bool,
+    // Leave `${...}` in the command alone, for shell syntax like `${VAR:-x}`.
+    pub interpolate: Option<bool>,
+    // Run the command with a shell even if it doesn't look like it needs one.
+    #[serde(default)]
+    pub force_shell: bool,
+    // Pass what the command writes to CARGO_CMD_ENV on to the commands after it.
+    #[serde(default)]
+    pub propagate_env: bool,
+    // Commands with the same mutex never run at the same time, not even from
+    // different runs of cargo-cmd.
+    pub mutex: Option<String>,
+    // Whether a service is started again when it exits, see `services`.
+    pub restart: Option<Restart>,
+    // Services in the same group that have to be ready before this one starts.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    // A service is ready once it prints a line containing `wait_for_log`
+    // and/or accepts connections on `wait_for_port` on localhost.
+    pub wait_for_log: Option<String>,
+    pub wait_for_port: Option<u16>,
+    // How long a service has to become ready, 30s by default.
+    pub startup_timeout: Option<String>,
+    // When a service's log in `stdout_file` or `stderr_file` is started over,
+    // and how many of the old ones are kept, see `logs`.
+    pub log_rotate: Option<String>,
+    pub log_keep: Option<usize>,
+    // Start the command and go on without waiting for it, see `jobs`.
+    #[serde(default)]
+    pub background: bool,
+    // The name a background command is kept track of under, see
+    // `jobs::prepare`.
+    #[serde(skip)]
+    pub job: Option<String>,
+    // Groups the command belongs to, for running them with --tag.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Hide the command and its output unless it fails.
+    #[serde(default)]
+    pub quiet: bool,
+    // Show how the command is run and what it used.
+    #[serde(default)]
+    pub verbose: bool,
+    // Whether `cmd` is the path of a script rather than a shell command.
+    #[serde(skip)]
+    pub script: bool,
+    // Whether the command is one of the steps of a list, see `Steps`.
+    #[serde(skip)]
+    pub step: bool,
+    // Set for each run of a matrix, see `matrix::expand`.
+    #[serde(skip)]
+    pub matrix_env: Vec<(String, String)>,
+    // Everything that isn't an option, so typos can be reported in strict mode.
+    #[serde(flatten)]
+    pub unknown: HashMap<String, toml::Value>,
+}
+
+// A readiness probe that has to succeed before the command is started.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaitFor {
+    pub cmd: String,
+    #[serde(default = "WaitFor::default_timeout")]
+    pub timeout: String,
+    #[serde(default = "WaitFor::default_interval")]
+    pub interval: String,
+    #[serde(flatten)]
+    pub unknown: HashMap<String, toml::Value>,
+}
+
+impl WaitFor {
+    fn default_timeout() -> String {
+        "30s".to_string()
+    }
+
+    fn default_interval() -> String {
+        "1s".to_string()
+    }
+}
+
+// `cargo build` run before the command, with the profile and target given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum NeedsBuild {
+    Always(bool),
+    With {
+        profile: Option<String>,
+        target: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restart {
+    #[default]
+    No,
+    OnFailure,
+    Always,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl Command {
+    // Keys in the command's table (and the tables nested in it) that don't
+    // mean anything to cargo-cmd, in dotted form.
+    pub fn unknown_keys(&self) -> Vec<String> {
+        let options = &self.options;
+        let mut keys: Vec<String> = options.unknown.keys().cloned().collect();
+        for param in &options.params {
+            keys.extend(param.unknown.keys().map(|key| format!("params.{}", key)));
+        }
+        if let Some(ref wait_for) = options.wait_for {
+            keys.extend(
+                wait_for
+                    .unknown
+                    .keys()
+                    .map(|key| format!("wait_for.{}", key)),
+            );
+        }
+        for (target, variant) in &options.target {
+            keys.extend(
+                variant
+                    .unknown_keys()
+                    .iter()
+                    .map(|key| format!("target.{}.{}", target, key)),
+            );
+        }
+        keys.sort();
+        keys
+    }
+}
+
+impl Options {
+    // `nice` and `priority` mean the same thing, so either can be used on
+    // every platform. This is the niceness it comes down to.
+    pub fn niceness(&self) -> Option<i32> {
+        self.nice.or_else(|| {
+            self.priority.map(|priority| match priority {
+                Priority::Idle => 19,
+                Priority::BelowNormal => 10,
+                Priority::Normal => 0,
+                Priority::AboveNormal => -5,
+                Priority::High => -10,
+            })
+        })
+    }
+
+    // What the command exiting with `exit_code` counts as, where 0 is success
+    // and failures keep their exit code.
+    pub fn status(&self, exit_code: i32) -> i32 {
+        let success = if self.success_codes.is_empty() {
+            exit_code == 0
+        } else {
+            self.success_codes.contains(&exit_code)
+        };
+        match (success != self.expect_failure, exit_code) {
+            (true, _) => 0,
+            (false, 0) => 1,
+            (false, exit_code) => exit_code,
+        }
+    }
+
+    pub fn limits(&self) -> Result<Limits, String> {
+        Ok(Limits {
+            memory: match self.max_memory {
+                Some(ref max_memory) => Some(size::parse(max_memory)?),
+                None => None,
+            },
+            cpu_time: match self.max_cpu_time {
+                Some(ref max_cpu_time) => Some(duration::parse(max_cpu_time)?),
+                None => None,
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum RawCommand {
+    Shell(String),
+    Table {
+        cmd: String,
+        #[serde(flatten)]
+        options: Box<Options>,
+    },
+    Script {
+        script: String,
+        #[serde(flatten)]
+        options: Box<Options>,
+    },
+}
+
+// Tables are read whole before their options are, rather than tried against
+// each variant in turn, so that an invalid option is reported by its key.
+impl<'de> Deserialize<'de> for RawCommand {
+    fn deserialize<D>(deserializer: D) -> Result<RawCommand, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut table = match toml::Value::deserialize(deserializer)? {
+            toml::Value::String(cmd) => return Ok(RawCommand::Shell(cmd)),
+            toml::Value::Table(table) => table,
+            other => {
+                return Err(D::Error::custom(format!(
+                    "invalid type: {}, expected a string or a table",
+                    other.type_str()
+                )))
+            }
+        };
+        let mut string = |key: &str| match table.remove(key) {
+            Some(toml::Value::String(value)) => Ok(Some(value)),
+            Some(other) => Err(D::Error::custom(format!(
+                "invalid type: {}, expected a string for key `{}`",
+                other.type_str(),
+                key
+            ))),
+            None => Ok(None),
+        };
+        let cmd = string("cmd")?;
+        // With a `cmd`, `script` is just a key cargo-cmd doesn't know.
+        let script = match cmd {
+            Some(_) => None,
+            None => string("script")?,
+        };
+        let options = toml::Value::Table(table)
+            .try_into()
+            .map_err(D::Error::custom)?;
+        match (cmd, script) {
+            (Some(cmd), _) => Ok(RawCommand::Table { cmd, options }),
+            (None, Some(script)) => Ok(RawCommand::Script { script, options }),
+            (None, None) => Err(D::Error::custom("missing field `cmd`")),
+        }
+    }
+}
+
+impl From<RawCommand> for Command {
+    fn from(raw: RawCommand) -> Command {
+        match raw {
+            RawCommand::Shell(cmd) => Command::from(cmd),
+            RawCommand::Table { cmd, options } => Command {
+                cmd,
+                options: *options,
+            },
+            RawCommand::Script { script, options } => Command {
+                cmd: script,
+                options: Options {
+                    script: true,
+                    ..*options
+                },
+            },
+        }
+    }
+}
+
+impl From<Command> for RawCommand {
+    fn from(command: Command) -> RawCommand {
+        if command.options.script {
+            RawCommand::Script {
+                script: command.cmd,
+                options: Box::new(command.options),
+            }
+        } else {
+            RawCommand::Table {
+                cmd: command.cmd,
+                options: Box::new(command.options),
+            }
+        }
+    }
+}
+
+impl From<String> for Command {
+    fn from(cmd: String) -> Command {
+        Command {
+            cmd,
+            options: Options::default(),
+        }
+    }
+}
+
+// A command, or a list of commands that run one after the other, like
+// `pretest = ["docker compose up -d", "diesel migration run"]`. The ones in a
+// list become steps named `pretest.1`, `pretest.2` and so on.
+enum Steps {
+    One(Box<Command>),
+    Many(Vec<Command>),
+}
+
+// Each command is read on its own, so that an invalid one is reported by its
+// name.
+pub fn deserialize_steps<'de, D>(deserializer: D) -> Result<HashMap<String, Command>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut commands = HashMap::new();
+    for (name, value) in HashMap::<String, toml::Value>::deserialize(deserializer)? {
+        let steps = match value {
+            toml::Value::Array(steps) => steps
+                .into_iter()
+                .map(toml::Value::try_into)
+                .collect::<Result<_, _>>()
+                .map(Steps::Many),
+            command => command
+                .try_into()
+                .map(|command| Steps::One(Box::new(command))),
+        };
+        let steps =
+            steps.map_err(|error| D::Error::custom(format!("command \"{}\": {}", name, error)))?;
+        match steps {
+            Steps::One(command) => {
+                commands.insert(name, *command);
+            }
+            Steps::Many(steps) => {
+                for (index, mut command) in steps.into_iter().enumerate() {
+                    command.options.step = true;
+                    commands.insert(format!("{}.{}", name, index + 1), command);
+                }
+            }
+        }
+    }
+    Ok(commands)
+}
+
+// The name of the list the step `name` belongs to, or `name` itself for
+// commands that aren't steps, even if their names end in `.<number>` like
+// `py3.11`.
+pub fn base_name<'a>(name: &'a str, command: &Command) -> &'a str {
+    match name.rsplit_once('.') {
+        Some((base, _)) if command.options.step => base,
+        _ => name,
+    }
+}
+
+// What runs as `name`: the command, or its steps in order.
+pub fn steps<'a>(commands: &'a HashMap<String, Command>, name: &str) -> Vec<&'a String> {
+    let mut steps: Vec<&String> = commands
+        .keys()
+        .filter(|step| *step == name || base_name(step, &commands[*step]) == name)
+        .collect();
+    steps.sort_by_key(|step| {
+        step[name.len()..]
+            .trim_start_matches('.')
+            .parse::<usize>()
+            .unwrap_or(0)
+    });
+    steps
+}
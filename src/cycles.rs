@@ -0,0 +1,132 @@
+// A command that ends up running itself again, through a `cargo cmd` call in
+// it or in one of the hooks that run with it, would never finish. Such loops
+// are reported before anything runs.
+
+use command::{self, Command};
+use std::collections::{HashMap, HashSet};
+use {SESSION_COMMANDS, WILDCARD_HOOKS};
+
+// The commands that run for `cargo cmd <name>`.
+fn chain<'a>(commands: &'a HashMap<String, Command>, name: &str) -> Vec<&'a String> {
+    let mut names = vec![
+        format!("pre{}", name),
+        name.to_string(),
+        format!("post{}", name),
+    ];
+    if !WILDCARD_HOOKS.contains(&name) {
+        names.extend(WILDCARD_HOOKS.iter().map(|hook| hook.to_string()));
+    }
+    if !SESSION_COMMANDS.contains(&name) {
+        names.extend(SESSION_COMMANDS.iter().map(|command| command.to_string()));
+    }
+    names
+        .iter()
+        .flat_map(|name| command::steps(commands, name))
+        .collect()
+}
+
+// Flags of cargo-cmd that take a value.
+const VALUE_FLAGS: &[&str] = &[
+    "--max-time",
+    "--trace",
+    "--target",
+    "--profile",
+    "--from",
+    "--skip",
+    "--since",
+    "--features",
+];
+// Flags that make cargo-cmd run something other than the command it's given,
+// or take it from another manifest.
+const OTHER_RUNS: &[&str] = &[
+    "--exec",
+    "--tag",
+    "--explain",
+    "--stop",
+    "--logs",
+    "--verify-ci",
+    "--compare",
+    "--clean",
+    "-C",
+    "--chdir",
+];
+
+// The command a `cargo cmd` call with these arguments runs.
+fn called<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            _ if OTHER_RUNS.contains(arg) => return None,
+            _ if VALUE_FLAGS.contains(arg) => {
+                args.next();
+            }
+            _ if arg.starts_with('-') => {}
+            command => return Some(command),
+        }
+    }
+    None
+}
+
+// The commands `cmd` runs with `cargo cmd <command>`.
+pub fn calls(cmd: &str) -> Vec<&str> {
+    let words: Vec<&str> = cmd
+        .split(|c: char| c.is_whitespace() || "'\";&|()".contains(c))
+        .filter(|word| !word.is_empty())
+        .collect();
+    words
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair == &["cargo", "cmd"])
+        .filter_map(|(index, _)| called(&words[index + 2..]))
+        .collect()
+}
+
+fn visit<'a>(
+    commands: &'a HashMap<String, Command>,
+    name: &'a str,
+    path: &mut Vec<String>,
+    finished: &mut HashSet<&'a str>,
+) -> Result<(), String> {
+    if finished.contains(name) {
+        return Ok(());
+    }
+    for member in chain(commands, name) {
+        for called in calls(&commands[member].cmd) {
+            let called = match commands.get_key_value(called) {
+                Some((called, _)) => called,
+                None => continue,
+            };
+            let step = if member == name {
+                called.to_string()
+            } else {
+                format!("{} (from {})", called, member)
+            };
+            let looped = path
+                .iter()
+                .position(|step| step.split(' ').next() == Some(called));
+            path.push(step);
+            if let Some(start) = looped {
+                return Err(format!(
+                    "Commands run each other in a loop: {}",
+                    path[start..].join(" -> ")
+                ));
+            }
+            visit(commands, called, path, finished)?;
+            path.pop();
+        }
+    }
+    finished.insert(name);
+    Ok(())
+}
+
+pub fn check(commands: &HashMap<String, Command>, command: &str) -> Result<(), String> {
+    match commands.get_key_value(command) {
+        Some((command, _)) => visit(
+            commands,
+            command,
+            &mut vec![command.to_string()],
+            &mut HashSet::new(),
+        ),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Parse durations like `500ms`, `30s`, `10m` or `1h`. A bare number is taken
+// as seconds.
+pub fn parse(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount
+        .parse()
+        .or(Err(format!("Invalid duration \"{}\"", value)))?;
+    let seconds = match unit.trim() {
+        "ms" => amount / 1000.0,
+        "" | "s" => amount,
+        "m" => amount * 60.0,
+        "h" => amount * 60.0 * 60.0,
+        _ => {
+            return Err(format!(
+                "Invalid duration \"{}\", expected a unit of ms, s, m or h",
+                value
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(seconds).or(Err(format!(
+        "Invalid duration \"{}\", it's too long",
+        value
+    )))
+}
+
+// The year, month and day `days` days after 1970-01-01, using the days to
+// civil date conversion from http://howardhinnant.github.io/date_algorithms.html.
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// The number of days from 1970-01-01 to the given date, the other way around.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The time in UTC, like `2026-10-16T09:30:00Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    let seconds = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Read back a time written by `timestamp`.
+pub fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let bytes = timestamp.as_bytes();
+    let separators = [
+        (4, b'-'),
+        (7, b'-'),
+        (10, b'T'),
+        (13, b':'),
+        (16, b':'),
+        (19, b'Z'),
+    ];
+    if bytes.len() != 20
+        || separators
+            .iter()
+            .any(|&(index, separator)| bytes[index] != separator)
+    {
+        return None;
+    }
+    let number = |start: usize, end: usize| -> Option<u32> {
+        let digits = &timestamp[start..end];
+        if !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let days = days_from_civil(number(0, 4)? as i64, number(5, 7)?, number(8, 10)?);
+    let seconds = number(11, 13)? * 3600 + number(14, 16)? * 60 + number(17, 19)?;
+    if days < 0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::from_secs(days as u64 * 86400 + seconds as u64))
+}
+
+// Format a duration for people, like `1.25s`.
+pub fn format(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// Read a dotenv style file: `KEY=value` lines, optionally prefixed with
+// `export`, with `#` comments and single or double quoted values.
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let mut file = File::open(path).or(Err(format!(
+        "Could not find or open env file {}",
+        path.display()
+    )))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .or(Err(format!("Could not read env file {}", path.display())))?;
+
+    let mut vars = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_start_matches("export ");
+        let (key, value) = match line.find('=') {
+            Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+            None => {
+                return Err(format!(
+                    "Invalid line {} in env file {}, expected KEY=value",
+                    index + 1,
+                    path.display()
+                ))
+            }
+        };
+        vars.push((key.to_string(), unquote(value).to_string()));
+    }
+    Ok(vars)
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in &['"', '\''] {
+        if value.len() >= 2 && value.starts_with(*quote) && value.ends_with(*quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    // Unquoted values can have a trailing comment.
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end(),
+        None => value,
+    }
+}
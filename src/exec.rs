@@ -0,0 +1,624 @@
+use builtins;
+use command::{Command, WaitFor};
+use duration;
+use env_file;
+use glob::{MatchOptions, Pattern};
+use interpolate;
+use jobs;
+use params;
+use process_tree::{self, ProcessTree, Usage};
+use quote;
+use script;
+use shell_words;
+use size;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use subprocess::{Exec, ExitStatus, NullFile, Popen, PopenConfig, Redirection};
+use term;
+
+#[cfg(unix)]
+pub const SHELL: [&str; 2] = ["sh", "-c"];
+#[cfg(windows)]
+pub const SHELL: [&str; 2] = ["cmd.exe", "/c"];
+
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+#[cfg(windows)]
+const NULL_DEVICE: &str = "nul";
+
+// Variable names are case insensitive on Windows.
+const ENV_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: cfg!(unix),
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+// Characters that mean something to the shell, outside of quotes.
+const SHELL_SYNTAX: &str = "|&;<>()$`\\*?[]{}#~!\n";
+
+// How long a command gets to exit after being asked to before it's killed.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+thread_local! {
+    // What the processes started on this thread used since `take_usage` was
+    // last called, so parallel runs each get their own.
+    static USAGE: Cell<Option<Usage>> = const { Cell::new(None) };
+}
+
+// What the commands that ran on this thread since the last call used, added
+// up. A command with a script can run several.
+pub fn take_usage() -> Option<Usage> {
+    USAGE.with(Cell::take)
+}
+
+fn add_usage(usage: Usage) {
+    USAGE.with(|total| {
+        let mut sum = total.take().unwrap_or_default();
+        sum.user += usage.user;
+        sum.system += usage.system;
+        sum.peak_memory = sum.peak_memory.max(usage.peak_memory);
+        total.set(Some(sum));
+    });
+}
+
+// Cargo tells subcommands where it lives, which matters when several
+// toolchains are installed.
+pub fn cargo() -> OsString {
+    env::var_os("CARGO").unwrap_or_else(|| "cargo".into())
+}
+
+// Where `program` is found on `search_path`. On Windows `node` can also be
+// `node.exe`, `node.cmd`, ...
+pub fn find_program(program: &str, search_path: Option<&OsStr>) -> Option<PathBuf> {
+    let paths = search_path?;
+    let mut extensions = vec![String::new()];
+    if cfg!(windows) {
+        let pathext = env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.BAT;.CMD".to_string());
+        extensions.extend(pathext.split(';').map(str::to_string));
+    }
+
+    env::split_paths(paths)
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |extension| dir.join(format!("{}{}", program, extension)))
+        })
+        .find(|path| path.is_file())
+}
+
+// The program and arguments to run the command with directly, saving a shell,
+// when it doesn't use anything only the shell understands. Programs that
+// aren't on the command's PATH, like `cd` or `time` that the shell runs itself,
+// are left to the shell too. cmd.exe runs a lot of its commands itself, so
+// Windows always gets a shell.
+fn direct_argv(command: &Command, cmd: &str, env: &[(OsString, OsString)]) -> Option<Vec<String>> {
+    if cfg!(windows) || command.options.force_shell || cmd.contains(|c| SHELL_SYNTAX.contains(c)) {
+        return None;
+    }
+    let mut words = shell_words::split(cmd).ok()?;
+    let program = words.first()?;
+    if program.contains('=') {
+        return None;
+    }
+    if !program.contains('/') {
+        let path = env
+            .iter()
+            .rev()
+            .find(|(name, _)| name == "PATH")
+            .map(|(_, path)| &path[..]);
+        let found = find_program(program, path)?;
+        // Programs are looked up on our PATH rather than the command's.
+        if !command.options.path_prepend.is_empty() {
+            words[0] = found.to_string_lossy().into_owned();
+        }
+    }
+    Some(words)
+}
+
+// The file commands with `propagate_env` write `KEY=value` lines to, passed to
+// them in CARGO_CMD_ENV, for the commands that run after them.
+pub fn propagated_env_path() -> PathBuf {
+    env::temp_dir().join(format!("cargo-cmd-env-{}", process::id()))
+}
+
+// Everything about the current `cargo cmd` invocation that applies to each
+// command it runs.
+#[derive(Clone)]
+pub struct Invocation {
+    pub env: Vec<(String, String)>,
+    // The values of the params, also passed to the commands as variables.
+    pub values: HashMap<String, String>,
+    // Values that only fill in placeholders, like `${metadata.*}`.
+    pub placeholders: HashMap<String, String>,
+    pub rest: Vec<String>,
+    pub deadline: Option<Instant>,
+    pub report_usage: bool,
+}
+
+impl Invocation {
+    // Everything the placeholders of a command are filled in with. Params
+    // take precedence.
+    pub fn vars(&self) -> HashMap<String, String> {
+        let mut vars = self.placeholders.clone();
+        vars.extend(self.values.clone());
+        vars
+    }
+}
+
+fn patterns(option: &str, patterns: &Option<Vec<String>>) -> Result<Vec<Pattern>, String> {
+    patterns
+        .iter()
+        .flatten()
+        .map(|pattern| {
+            Pattern::new(pattern).or(Err(format!(
+                "Invalid pattern \"{}\" in {}",
+                pattern, option
+            )))
+        })
+        .collect()
+}
+
+// The PATH the command runs with, with the directories in `path_prepend` in
+// front of `path`. They're relative to the directory cargo-cmd runs in, not
+// wherever the command might change to.
+pub fn search_path(command: &Command, path: Option<&OsStr>) -> Result<Option<OsString>, String> {
+    let prepend = &command.options.path_prepend;
+    if prepend.is_empty() {
+        return Ok(path.map(OsStr::to_os_string));
+    }
+    let current_dir = env::current_dir().or(Err("Could not determine the current directory"))?;
+    let dirs = prepend
+        .iter()
+        .map(|dir| current_dir.join(dir))
+        .chain(path.into_iter().flat_map(env::split_paths));
+    env::join_paths(dirs).map(Some).or(Err(
+        "Could not add the path_prepend directories to PATH".to_string()
+    ))
+}
+
+// The environment a command runs with: ours, filtered by `env_clear`,
+// `env_allow` and `env_deny`, plus the invocation's and the command's own
+// variables.
+fn command_env(
+    command: &Command,
+    invocation: &Invocation,
+) -> Result<Vec<(OsString, OsString)>, String> {
+    let options = &command.options;
+    let allow = patterns("env_allow", &options.env_allow)?;
+    let deny = patterns("env_deny", &options.env_deny)?;
+    let matches = |patterns: &[Pattern], name: &OsString| {
+        let name = name.to_string_lossy();
+        patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(&name, ENV_MATCH))
+    };
+    let mut env: Vec<(OsString, OsString)> = PopenConfig::current_env()
+        .into_iter()
+        .filter(|(name, _)| !options.env_clear.unwrap_or(false) || matches(&allow, name))
+        .filter(|(name, _)| !matches(&deny, name))
+        .collect();
+    if !options.path_prepend.is_empty() {
+        let is_path = |name: &OsString| match name.to_str() {
+            Some(name) if cfg!(windows) => name.eq_ignore_ascii_case("PATH"),
+            Some(name) => name == "PATH",
+            None => false,
+        };
+        let path = env
+            .iter()
+            .position(|(name, _)| is_path(name))
+            .map(|index| env.remove(index).1);
+        if let Some(path) = search_path(command, path.as_deref())? {
+            env.push((OsString::from("PATH"), path));
+        }
+    }
+    let mut set_var = |name: String, value: String| {
+        env.push((OsString::from(name), OsString::from(value)));
+    };
+    for (name, value) in invocation.env.iter().chain(&options.matrix_env) {
+        set_var(name.to_string(), value.to_string());
+    }
+    if let Some(ref path) = options.bin_path {
+        set_var("CARGO_CMD_BIN".to_string(), path.to_string());
+    }
+    if let Some(ref toolchain) = options.toolchain {
+        set_var("RUSTUP_TOOLCHAIN".to_string(), toolchain.to_string());
+    }
+    if options.propagate_env {
+        let path = propagated_env_path().to_string_lossy().into_owned();
+        set_var("CARGO_CMD_ENV".to_string(), path);
+    }
+    if let Some(ref env_file) = command.options.env_file {
+        for (name, value) in env_file::load(Path::new(env_file))? {
+            set_var(name, value);
+        }
+    }
+    for (name, value) in &invocation.values {
+        set_var(params::env_name(name), value.to_string());
+    }
+    Ok(env)
+}
+
+// Where the command reads its input from: `inherit` (the default) passes ours
+// on, `null` gives it nothing and anything else is a file to read.
+fn stdin(command: &Command) -> Result<Redirection, String> {
+    let path = match command.options.stdin.as_deref() {
+        None | Some("inherit") => return Ok(Redirection::None),
+        Some("null") => NULL_DEVICE,
+        Some(path) => path,
+    };
+    File::open(path)
+        .map(Redirection::File)
+        .or(Err(format!("Could not find or open {}", path)))
+}
+
+// Where the command's output goes, if it's written to a file. The same file
+// for both streams is opened once, so the output isn't written over itself.
+fn output(command: &Command) -> Result<(Redirection, Redirection), String> {
+    let options = &command.options;
+    let open = |path: &String| {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(options.append)
+            .truncate(!options.append)
+            .open(path)
+            .map(Redirection::File)
+            .or(Err(format!("Could not open {} for writing", path)))
+    };
+    let stdout = match options.stdout_file {
+        Some(ref path) => open(path)?,
+        None => Redirection::None,
+    };
+    let stderr = match options.stderr_file {
+        Some(ref path) if options.stdout_file.as_ref() == Some(path) => Redirection::Merge,
+        Some(ref path) => open(path)?,
+        None => Redirection::None,
+    };
+    Ok((stdout, stderr))
+}
+
+// Start an interactive shell with the environment the command would get, to
+// debug what the command sees.
+pub fn spawn_shell(
+    name: &str,
+    command: &Command,
+    invocation: &Invocation,
+) -> Result<ExitStatus, String> {
+    let mut env = command_env(command, invocation)?;
+    env.push((OsString::from("CARGO_CMD_SHELL_ENV"), OsString::from(name)));
+    #[cfg(unix)]
+    let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    #[cfg(windows)]
+    let shell = env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+
+    eprintln!(
+        "Starting {} with the environment of \"{}\", exit the shell to return",
+        shell, name
+    );
+    Exec::cmd(&shell)
+        .env_clear()
+        .env_extend(&env)
+        .join()
+        .or(Err(format!("Could not start {}", shell)))
+}
+
+// Start a command without input and with its output piped back to us, in a
+// process group of its own on Unix, see `services`.
+pub fn spawn_piped(command: &Command, invocation: &Invocation) -> Result<Popen, String> {
+    let cmd = interpolate::command_line(command, &invocation.vars());
+    let cmd = builtins::expand(&cmd);
+    let config = PopenConfig {
+        stdin: Redirection::File(
+            File::open(NULL_DEVICE).or(Err("Could not open the null device"))?,
+        ),
+        stdout: Redirection::Pipe,
+        stderr: Redirection::Pipe,
+        env: Some(command_env(command, invocation)?),
+        cwd: command.options.cwd.as_ref().map(OsString::from),
+        #[cfg(unix)]
+        setpgid: true,
+        ..Default::default()
+    };
+    Popen::create(&[SHELL[0], SHELL[1], &cmd], config)
+        .or(Err(format!("Could not run \"{}\"", SHELL[0])))
+}
+
+// Run a command to completion. Returns `None` if the invocation's deadline
+// passed first, in which case the command has been terminated.
+pub fn execute_command(
+    command: &Command,
+    invocation: &Invocation,
+) -> Result<Option<ExitStatus>, String> {
+    let options = &command.options;
+    if options.quiet && options.stdout_file.is_none() && options.stderr_file.is_none() {
+        return execute_quietly(command, invocation);
+    }
+    // Held until the command is done.
+    let _mutex = match options.mutex {
+        Some(ref name) => match lock(name, invocation.deadline)? {
+            Some(file) => Some(file),
+            None if process_tree::interrupted() => return Ok(Some(ExitStatus::Exited(1))),
+            None => return Ok(None),
+        },
+        None => None,
+    };
+    // This is naughty but Exec::shell doesn't let us do it with .args because
+    // it ends up as an argument to sh/cmd.exe instead of our user command
+    // or escaping things weirdly.
+    let cmd = interpolate::command_line(command, &invocation.vars());
+    let cmd = format!("{} {}", cmd, quote::join(&invocation.rest));
+    let env = command_env(command, invocation)?;
+
+    if let Some(ref probe) = command.options.wait_for {
+        if !wait_for(probe, &env, invocation.deadline)? {
+            return Ok(None);
+        }
+    }
+
+    if command.options.script {
+        return script::run(command, invocation, env);
+    }
+
+    if !command.options.quiet {
+        term::status(&format!("> {}", cmd));
+    }
+    let cmd = builtins::expand(&cmd);
+    let direct = direct_argv(command, &cmd, &env);
+    let argv = match direct {
+        Some(ref words) => words.iter().map(|word| &word[..]).collect(),
+        None => vec![SHELL[0], SHELL[1], &cmd],
+    };
+    if command.options.verbose {
+        if let Some(ref cwd) = command.options.cwd {
+            eprintln!("In {}", cwd);
+        }
+        eprintln!("Running {:?}", argv);
+    }
+    spawn_and_wait(command, &cmd, &argv, env, invocation)
+}
+
+// Start `argv`, which is what runs for `cmd`, with the options of `command`
+// in a process tree of its own, and wait for it the way `execute_command`
+// does. Scripts run their commands with this too.
+pub fn spawn_and_wait(
+    command: &Command,
+    cmd: &str,
+    argv: &[&str],
+    env: Vec<(OsString, OsString)>,
+    invocation: &Invocation,
+) -> Result<Option<ExitStatus>, String> {
+    let options = &command.options;
+    let (stdout, stderr) = output(command)?;
+    let config = PopenConfig {
+        stdin: stdin(command)?,
+        stdout,
+        stderr,
+        env: Some(env),
+        cwd: command.options.cwd.as_ref().map(OsString::from),
+        #[cfg(unix)]
+        setpgid: true,
+        ..Default::default()
+    };
+    let limits = command.options.limits()?;
+    let limited =
+        process_tree::with_limits(&limits, argv.iter().map(|arg| arg.to_string()).collect());
+    let mut process = match Popen::create(&limited, config) {
+        Ok(process) => process,
+        Err(_) => return Err(format!("Could not run \"{}\"", argv[0])),
+    };
+    if let Some(ref job) = options.job {
+        process.detach();
+        jobs::record(job, cmd, &process, options.stdout_file.as_ref())?;
+        return Ok(Some(ExitStatus::Exited(0)));
+    }
+    let started = Instant::now();
+    let mut tree = ProcessTree::new(&process);
+    tree.set_limits(&limits)?;
+    if let Some(niceness) = command.options.niceness() {
+        if let Err(error) = tree.set_niceness(niceness) {
+            eprintln!("warning: {}", error);
+        }
+    }
+
+    let timeout = invocation
+        .deadline
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    let status = tree.wait(&mut process, timeout);
+    // Background processes ignore Ctrl-C, so clean up whatever the command
+    // left running once it has been interrupted.
+    if process_tree::interrupted() {
+        tree.terminate();
+    }
+    if let Ok(Some(_)) = status {
+        if let Some(usage) = tree.usage() {
+            add_usage(usage);
+        }
+        if invocation.report_usage || command.options.verbose {
+            report_usage(&tree, started.elapsed());
+        }
+    }
+    match status {
+        Ok(Some(status)) => Ok(Some(status)),
+        Ok(None) => {
+            tree.terminate();
+            if let Ok(None) = process.wait_timeout(GRACE_PERIOD) {
+                tree.kill();
+                process.wait().ok();
+            }
+            Ok(None)
+        }
+        Err(_) => Err(format!("Could not wait for \"{}\" to finish", argv[0])),
+    }
+}
+
+// Run a quiet command with its output kept in a file, which is only shown if
+// the command fails.
+fn execute_quietly(
+    command: &Command,
+    invocation: &Invocation,
+) -> Result<Option<ExitStatus>, String> {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let run = RUNS.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("cargo-cmd-quiet-{}-{}", process::id(), run));
+    let mut kept = command.clone();
+    kept.options.stdout_file = Some(path.to_string_lossy().into_owned());
+    kept.options.stderr_file = kept.options.stdout_file.clone();
+    kept.options.append = false;
+
+    let result = execute_command(&kept, invocation);
+    let succeeded = match result {
+        Ok(Some(ExitStatus::Exited(exit_code))) => command.options.status(exit_code as i32) == 0,
+        _ => false,
+    };
+    if !succeeded {
+        if let Ok(output) = fs::read_to_string(&path) {
+            print!("{}", output);
+        }
+    }
+    fs::remove_file(path).ok();
+    result
+}
+
+fn report_usage(tree: &ProcessTree, wall: Duration) {
+    let usage = match tree.usage() {
+        Some(usage) => usage,
+        None => return eprintln!("Took {}", duration::format(wall)),
+    };
+    eprintln!(
+        "Took {} ({} user, {} system, {} peak memory)",
+        duration::format(wall),
+        duration::format(usage.user),
+        duration::format(usage.system),
+        size::format(usage.peak_memory)
+    );
+}
+
+// Run `execute` for each of the commands at the same time, returning the exit
+// codes in the same order.
+pub fn execute_parallel<F>(commands: &[(String, Command)], execute: F) -> Vec<i32>
+where
+    F: Fn(&(String, Command)) -> i32 + Sync,
+{
+    let execute = &execute;
+    thread::scope(|scope| {
+        let runs: Vec<_> = commands
+            .iter()
+            .map(|command| scope.spawn(move || execute(command)))
+            .collect();
+        runs.into_iter()
+            .zip(commands)
+            .map(|(run, command)| {
+                run.join().unwrap_or_else(|_| {
+                    eprintln!("error: Running \"{}\" failed", command.0);
+                    1
+                })
+            })
+            .collect()
+    })
+}
+
+// `name` made safe to use as a file name.
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Wait until no other command with the mutex is running, in this run or
+// another one, and take it. It's let go of when the file is closed. Returns
+// `None` if the invocation's deadline passed or it was interrupted first.
+fn lock(name: &str, deadline: Option<Instant>) -> Result<Option<File>, String> {
+    let dir = Path::new("target/cargo-cmd/mutexes");
+    fs::create_dir_all(dir).or(Err(format!("Could not create {}", dir.display())))?;
+    let path = dir.join(format!("{}.lock", file_name(name)));
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .or(Err(format!("Could not open {}", path.display())))?;
+
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Some(file)),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(_)) => {
+                return Err(format!("Could not lock {}", path.display()))
+            }
+        }
+        if !waiting {
+            term::status(&format!("Waiting for mutex \"{}\"", name));
+            waiting = true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            || process_tree::interrupted()
+        {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// Run the probe until it succeeds. Returns `false` if the invocation's
+// deadline passed while waiting, and an error if the probe's timeout did.
+fn wait_for(
+    probe: &WaitFor,
+    env: &[(OsString, OsString)],
+    deadline: Option<Instant>,
+) -> Result<bool, String> {
+    let timeout = duration::parse(&probe.timeout)?;
+    let interval = duration::parse(&probe.interval)?;
+    let started = Instant::now();
+    let give_up = started + timeout;
+    term::status(&format!("Waiting for `{}`", probe.cmd));
+
+    loop {
+        let limit = match deadline {
+            Some(deadline) if deadline < give_up => deadline,
+            _ => give_up,
+        };
+        let mut process = Exec::shell(&probe.cmd)
+            .env_clear()
+            .env_extend(env)
+            .stdout(NullFile)
+            .stderr(NullFile)
+            .popen()
+            .or(Err(format!("Could not run `{}`", probe.cmd)))?;
+        let remaining = limit.saturating_duration_since(Instant::now());
+        match process.wait_timeout(remaining) {
+            Ok(Some(status)) if status.success() => return Ok(true),
+            Ok(Some(_)) => {}
+            _ => {
+                process.kill().ok();
+                process.wait().ok();
+            }
+        }
+
+        let now = Instant::now();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            return Ok(false);
+        }
+        if now + interval >= give_up {
+            return Err(format!(
+                "`{}` did not succeed within {}",
+                probe.cmd, probe.timeout
+            ));
+        }
+        thread::sleep(interval);
+    }
+}
@@ -0,0 +1,79 @@
+// A description of how `cargo cmd <command>` resolves, for --explain: which
+// files the commands come from and what runs in which order.
+
+use builtins;
+use command::{self, Command};
+use manifest::Origin;
+use matrix;
+use std::collections::HashMap;
+
+// Why `name` runs when `command` is asked for.
+fn role(name: &str, run: &Command, command: &str) -> Option<&'static str> {
+    // Steps have the role of the command they belong to.
+    let name = command::base_name(name, run);
+    match name {
+        _ if name == command => None,
+        "setup" => Some("session command"),
+        "teardown" => Some("session command, runs even if one before it failed"),
+        "pre*" | "post*" => Some("wildcard hook"),
+        _ if name.starts_with("pre") => Some("pre hook"),
+        _ => Some("post hook"),
+    }
+}
+
+pub fn origin(origins: &[Origin]) -> String {
+    let mut origins = origins.iter().rev().map(Origin::to_string);
+    let mut origin = format!("from {}", origins.next().unwrap_or_default());
+    let overridden: Vec<String> = origins.collect();
+    if !overridden.is_empty() {
+        origin.push_str(&format!(", overriding {}", overridden.join(", ")));
+    }
+    origin
+}
+
+pub fn print(
+    command: &str,
+    commands: &[&(String, Command)],
+    origins: &HashMap<String, Vec<Origin>>,
+    target: Option<&str>,
+    variants: &[String],
+) {
+    println!("`cargo cmd {}` runs, in this order:", command);
+    for (index, (name, run)) in commands.iter().map(|c| (&c.0, &c.1)).enumerate() {
+        println!("  {}. {}: {}", index + 1, name, run.cmd);
+
+        let mut details: Vec<String> = vec![];
+        details.extend(role(name, run, command).map(String::from));
+        if let Some(origins) = origins.get(name) {
+            details.push(origin(origins));
+            details.extend(origins.last().map(|origin| format!("as `{}`", origin.key)));
+        }
+        if !details.is_empty() {
+            println!("     {}", details.join(", "));
+        }
+
+        if let (Some(target), true) = (target, variants.contains(name)) {
+            println!("     using its variant for target {}", target);
+        }
+        let runs = matrix::expand(name, run).len();
+        if runs > 1 {
+            println!(
+                "     runs {} times for its matrix{}",
+                runs,
+                if run.options.parallel == Some(true) {
+                    ", in parallel"
+                } else {
+                    ""
+                }
+            );
+        }
+        let used: Vec<&str> = run
+            .cmd
+            .split_whitespace()
+            .filter(|word| builtins::is_builtin(word))
+            .collect();
+        if !used.is_empty() {
+            println!("     uses the built-in {}", used.join(", "));
+        }
+    }
+}
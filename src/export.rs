@@ -0,0 +1,188 @@
+use command::{self, Command};
+use exec;
+use serde_json::{self, json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const MARKDOWN_FILE: &str = "COMMANDS.md";
+const VSCODE_FILE: &str = ".vscode/tasks.json";
+// Tasks in tasks.json with a label starting with this are the ones we wrote,
+// and are replaced when exporting again. The others are left alone.
+const VSCODE_LABEL: &str = "cargo cmd: ";
+const IDEA_DIR: &str = ".idea/runConfigurations";
+// Run configurations we wrote are named after the command with this in front,
+// so the ones of commands that are gone can be removed.
+const IDEA_PREFIX: &str = "cargo_cmd_";
+
+// Text that can go in a table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+// Text in a table cell as inline code, with a longer fence when it contains
+// backticks itself.
+fn code(text: &str) -> String {
+    let text = cell(text);
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+// Hooks are listed with the command they belong to rather than on their own.
+fn is_hook(name: &str, commands: &HashMap<String, Command>) -> bool {
+    ["pre", "post"].iter().any(|prefix| {
+        command::base_name(name, &commands[name])
+            .strip_prefix(prefix)
+            .is_some_and(|command| !command.is_empty() && commands.contains_key(command))
+    })
+}
+
+// The commands to export, in order.
+fn names(commands: &HashMap<String, Command>) -> Vec<&String> {
+    let mut names: Vec<&String> = commands
+        .keys()
+        .filter(|name| !is_hook(name, commands))
+        .collect();
+    names.sort();
+    names
+}
+
+fn markdown(commands: &HashMap<String, Command>) -> String {
+    let mut markdown = String::from(
+        "# Commands\n\n\
+         Generated by `cargo cmd --export markdown`, run them with `cargo cmd <command>`.\n\n\
+         | Command | Description | Definition | Hooks |\n\
+         | --- | --- | --- | --- |\n",
+    );
+    for name in names(commands) {
+        let command = &commands[name];
+        let hooks: Vec<String> = [format!("pre{}", name), format!("post{}", name)]
+            .iter()
+            .flat_map(|hook| command::steps(commands, hook))
+            .map(|hook| format!("{}: {}", code(hook), code(&commands[hook].cmd)))
+            .collect();
+        markdown.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            code(name),
+            cell(command.options.description.as_deref().unwrap_or("")),
+            code(&command.cmd),
+            hooks.join("<br>")
+        ));
+    }
+    markdown
+}
+
+// A VS Code task for each command, in place of the ones exported before.
+// Commands that run cargo get its problem matcher, so errors show up in the
+// editor, and `build` and `test` go in the matching task groups.
+fn vscode(commands: &HashMap<String, Command>) -> Result<String, String> {
+    let mut tasks_json: Value = match fs::read_to_string(VSCODE_FILE) {
+        Ok(contents) => serde_json::from_str(&contents).or(Err(format!(
+            "Could not parse {}, it can't have comments for it to be updated",
+            VSCODE_FILE
+        )))?,
+        Err(_) => json!({ "version": "2.0.0" }),
+    };
+    let tasks = tasks_json
+        .as_object_mut()
+        .ok_or(format!("{} is not a JSON object", VSCODE_FILE))?
+        .entry("tasks")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .ok_or(format!("The tasks in {} are not a list", VSCODE_FILE))?;
+    tasks.retain(|task| {
+        !task["label"]
+            .as_str()
+            .is_some_and(|label| label.starts_with(VSCODE_LABEL))
+    });
+    for name in names(commands) {
+        let command = &commands[name];
+        let mut task = json!({
+            "label": format!("{}{}", VSCODE_LABEL, name),
+            "type": "shell",
+            "command": format!("cargo cmd {}", name),
+            "problemMatcher": if command.cmd.starts_with("cargo ") {
+                json!(["$rustc"])
+            } else {
+                json!([])
+            },
+        });
+        if let Some(ref description) = command.options.description {
+            task["detail"] = json!(description);
+        }
+        if name == "build" || name == "test" {
+            task["group"] = json!(name);
+        }
+        tasks.push(task);
+    }
+    serde_json::to_string_pretty(&tasks_json).or(Err("Could not export the tasks".to_string()))
+}
+
+fn xml_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+// A shell script run configuration for each command, which JetBrains IDEs
+// pick up from .idea/runConfigurations, in place of the ones exported before.
+fn idea(commands: &HashMap<String, Command>) -> Result<usize, String> {
+    fs::create_dir_all(IDEA_DIR).or(Err(format!("Could not create {}", IDEA_DIR)))?;
+    for entry in fs::read_dir(IDEA_DIR).into_iter().flatten().flatten() {
+        if entry.file_name().to_string_lossy().starts_with(IDEA_PREFIX) {
+            fs::remove_file(entry.path()).ok();
+        }
+    }
+    let names = names(commands);
+    for name in &names {
+        let path =
+            Path::new(IDEA_DIR).join(format!("{}{}.xml", IDEA_PREFIX, exec::file_name(name)));
+        let contents = format!(
+            "<component name=\"ProjectRunConfigurationManager\">\n  \
+             <configuration default=\"false\" name=\"cargo cmd: {}\" type=\"ShConfigurationType\">\n    \
+             <option name=\"SCRIPT_TEXT\" value=\"cargo cmd {}\" />\n    \
+             <option name=\"INDEPENDENT_SCRIPT_PATH\" value=\"true\" />\n    \
+             <option name=\"SCRIPT_PATH\" value=\"\" />\n    \
+             <option name=\"SCRIPT_OPTIONS\" value=\"\" />\n    \
+             <option name=\"INDEPENDENT_SCRIPT_WORKING_DIRECTORY\" value=\"true\" />\n    \
+             <option name=\"SCRIPT_WORKING_DIRECTORY\" value=\"$PROJECT_DIR$\" />\n    \
+             <option name=\"INDEPENDENT_INTERPRETER_PATH\" value=\"true\" />\n    \
+             <option name=\"INTERPRETER_PATH\" value=\"\" />\n    \
+             <option name=\"INTERPRETER_OPTIONS\" value=\"\" />\n    \
+             <option name=\"EXECUTE_IN_TERMINAL\" value=\"true\" />\n    \
+             <option name=\"EXECUTE_SCRIPT_FILE\" value=\"false\" />\n    \
+             <envs />\n    \
+             <method v=\"2\" />\n  \
+             </configuration>\n\
+             </component>\n",
+            xml_attribute(name),
+            xml_attribute(name)
+        );
+        fs::write(&path, contents).or(Err(format!("Could not write {}", path.display())))?;
+    }
+    Ok(names.len())
+}
+
+pub fn run(format: &str, commands: &HashMap<String, Command>) -> Result<(), String> {
+    if format == "idea" {
+        let count = idea(commands)?;
+        println!("Wrote {} run configurations to {}", count, IDEA_DIR);
+        return Ok(());
+    }
+    let (path, contents) = match format {
+        "markdown" => (MARKDOWN_FILE, markdown(commands)),
+        "vscode" => (VSCODE_FILE, vscode(commands)? + "\n"),
+        _ => return Err(format!("Unknown export format \"{}\"", format)),
+    };
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).or(Err(format!("Could not create {}", dir.display())))?;
+    }
+    fs::write(path, contents).or(Err(format!("Could not write {}", path)))?;
+    println!("Wrote {}", path);
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use subprocess::{Exec, NullFile, Redirection};
+
+fn git(args: &[&str]) -> Option<String> {
+    let capture = Exec::cmd("git")
+        .args(args)
+        .stdout(Redirection::Pipe)
+        .stderr(NullFile)
+        .capture()
+        .ok()?;
+    if capture.success() {
+        Some(capture.stdout_str().trim().to_string())
+    } else {
+        None
+    }
+}
+
+// What `${git(...)}` placeholders are replaced with: the `sha` or `short_sha`
+// of HEAD, or the `branch`.
+pub fn info(what: &str) -> Option<String> {
+    match what {
+        "sha" => git(&["rev-parse", "HEAD"]),
+        "short_sha" => git(&["rev-parse", "--short", "HEAD"]),
+        "branch" => git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+        _ => None,
+    }
+}
+
+// The full sha of the commit `rev`, like `HEAD~1`, refers to.
+pub fn resolve(rev: &str) -> Option<String> {
+    git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{}^{{commit}}", rev),
+    ])
+}
+
+// Information about the git checkout the commands run in, exposed to them as
+// environment variables. Outside of a git repository there is nothing to add.
+pub fn env() -> Vec<(String, String)> {
+    let sha = match git(&["rev-parse", "HEAD"]) {
+        Some(sha) => sha,
+        None => return vec![],
+    };
+    let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]).unwrap_or_default();
+    let dirty = git(&["status", "--porcelain"]).is_some_and(|status| !status.is_empty());
+
+    vec![
+        ("CARGO_CMD_GIT_SHA".to_string(), sha),
+        ("CARGO_CMD_GIT_BRANCH".to_string(), branch),
+        ("CARGO_CMD_GIT_DIRTY".to_string(), dirty.to_string()),
+    ]
+}
@@ -0,0 +1,112 @@
+use command::Command;
+use duration;
+use git;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Replace `${name}` placeholders for the known variables, and `${function(arg)}`
+// ones for the functions below. Anything else is left alone, so shell
+// parameter expansion like `${HOME}` keeps working, and so are placeholders
+// escaped as `$${...}`, which `command_line` turns into `${...}` once all the
+// placeholders are filled in.
+pub fn interpolate(command: &str, vars: &HashMap<String, String>) -> String {
+    let mut interpolated = String::new();
+    let mut rest = command;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        if rest[..start].ends_with('$') {
+            interpolated.push_str(&rest[..=end]);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        interpolated.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..end];
+        match vars.get(placeholder).cloned().or_else(|| call(placeholder)) {
+            Some(value) => interpolated.push_str(&value),
+            None => interpolated.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    interpolated.push_str(rest);
+    interpolated
+}
+
+// What runs for `command`, with its placeholders filled in, unless it has
+// `interpolate = false`.
+pub fn command_line(command: &Command, vars: &HashMap<String, String>) -> String {
+    if command.options.interpolate == Some(false) {
+        return command.cmd.clone();
+    }
+    interpolate(&command.cmd, vars).replace("$${", "${")
+}
+
+// The value of a placeholder like `date(%Y%m%d)`, if it's a call to one of
+// the functions.
+fn call(placeholder: &str) -> Option<String> {
+    let (function, arg) = placeholder.strip_suffix(')')?.split_once('(')?;
+    match function {
+        "env" => Some(env::var(arg).unwrap_or_default()),
+        "uuid" if arg.is_empty() => Some(uuid()),
+        "date" => Some(date(arg, SystemTime::now())),
+        "git" => git::info(arg),
+        _ => None,
+    }
+}
+
+// A random version 4 UUID.
+fn uuid() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    let (high, low) = (random(), random());
+    let high = (high & !0xf000) | 0x4000;
+    let low = (low & !(0xc_u64 << 60)) | (0x8_u64 << 60);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+// `time` in UTC, formatted like strftime with `%Y`, `%m`, `%d`, `%H`, `%M`,
+// `%S`, `%s` for the Unix time and `%%`.
+fn date(format: &str, time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+    let (year, month, day) = duration::civil_date(seconds.div_euclid(86_400));
+    let of_day = seconds.rem_euclid(86_400);
+
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(&year.to_string()),
+            Some('m') => formatted.push_str(&format!("{:02}", month)),
+            Some('d') => formatted.push_str(&format!("{:02}", day)),
+            Some('H') => formatted.push_str(&format!("{:02}", of_day / 3600)),
+            Some('M') => formatted.push_str(&format!("{:02}", of_day / 60 % 60)),
+            Some('S') => formatted.push_str(&format!("{:02}", of_day % 60)),
+            Some('s') => formatted.push_str(&seconds.to_string()),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
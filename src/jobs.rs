@@ -0,0 +1,173 @@
+// Commands with `background = true` are started and left running while the
+// commands after them go on, like `&` in a shell. Each one is kept track of
+// in target/cargo-cmd/jobs, so --jobs-list can show them and --stop can stop
+// them from a later run.
+
+use command::Command;
+use exec;
+use process_tree;
+use serde_json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use subprocess::Popen;
+use term;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Job {
+    name: String,
+    pid: u32,
+    cmd: String,
+    log: Option<String>,
+}
+
+fn dir() -> PathBuf {
+    Path::new("target/cargo-cmd/jobs").to_path_buf()
+}
+
+fn path(name: &str) -> PathBuf {
+    dir().join(format!("{}.json", exec::file_name(name)))
+}
+
+fn default_log(name: &str) -> PathBuf {
+    dir().join(format!("{}.log", exec::file_name(name)))
+}
+
+// The command set up to run as the job `name`: without input, since it isn't
+// in the foreground, and with its output in a log file unless it already goes
+// somewhere.
+pub fn prepare(name: &str, command: &Command) -> Command {
+    let mut command = command.clone();
+    let options = &mut command.options;
+    options.job = Some(name.to_string());
+    if options.stdout_file.is_none() && options.stderr_file.is_none() {
+        fs::create_dir_all(dir()).ok();
+        options.stdout_file = Some(default_log(name).to_string_lossy().into_owned());
+        options.stderr_file = options.stdout_file.clone();
+        options.append = false;
+    }
+    if options.stdin.is_none() {
+        options.stdin = Some("null".to_string());
+    }
+    command
+}
+
+// Keep track of the process started for the job `name`.
+pub fn record(name: &str, cmd: &str, process: &Popen, log: Option<&String>) -> Result<(), String> {
+    let job = Job {
+        name: name.to_string(),
+        pid: process.pid().unwrap_or(0),
+        cmd: cmd.to_string(),
+        log: log.cloned(),
+    };
+    let path = path(name);
+    fs::create_dir_all(dir()).or(Err(format!("Could not create {}", dir().display())))?;
+    let contents = serde_json::to_string(&job).or(Err("Could not save the job"))?;
+    fs::write(&path, contents).or(Err(format!("Could not write {}", path.display())))?;
+    let mut status = format!("Started `{}` in the background (pid {})", name, job.pid);
+    if let Some(log) = log {
+        status.push_str(&format!(", its output goes to {}", log));
+    }
+    term::status(&status);
+    Ok(())
+}
+
+// The file the output of the job `name` went to, if it was started with its
+// output in one.
+pub fn log(name: &str) -> Option<String> {
+    let job: Option<Job> = fs::read_to_string(path(name))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok());
+    match job {
+        Some(job) => job.log,
+        None => Some(default_log(name))
+            .filter(|log| log.exists())
+            .map(|log| log.to_string_lossy().into_owned()),
+    }
+}
+
+fn jobs() -> Vec<(PathBuf, Job)> {
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut jobs: Vec<(PathBuf, Job)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|path| {
+            let job = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((path, job))
+        })
+        .collect();
+    jobs.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    jobs
+}
+
+// Print the jobs that are still running, and forget the ones that have exited.
+pub fn list() {
+    let mut running = 0;
+    for (path, job) in jobs() {
+        if !process_tree::is_running(job.pid) {
+            fs::remove_file(path).ok();
+            continue;
+        }
+        running += 1;
+        println!("{}  (pid {})  {}", job.name, job.pid, job.cmd);
+    }
+    if running == 0 {
+        println!("No jobs are running");
+    }
+}
+
+// Stop the job `name` along with everything it started.
+pub fn stop(name: &str) -> Result<(), String> {
+    let (path, job) = jobs()
+        .into_iter()
+        .find(|(_, job)| job.name == name)
+        .ok_or(format!("No job named \"{}\" is running", name))?;
+    fs::remove_file(path).ok();
+    if !process_tree::is_running(job.pid) {
+        return Err(format!("Job \"{}\" has already exited", name));
+    }
+    process_tree::stop(job.pid);
+    println!("Stopped `{}` (pid {})", name, job.pid);
+    Ok(())
+}
+
+// Remove the records of the jobs that have exited and, with `logs`, the logs
+// they left in target/cargo-cmd/jobs. The files of jobs that are still running
+// are kept. Returns what was removed.
+pub fn clean(records: bool, logs: bool) -> Vec<PathBuf> {
+    let mut running = vec![];
+    for (_, job) in jobs() {
+        if process_tree::is_running(job.pid) {
+            println!("Keeping the files of `{}`, it's still running", job.name);
+            running.push(exec::file_name(&job.name));
+        }
+    }
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut removed: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let is_job = path
+                .file_stem()
+                .is_some_and(|stem| !running.iter().any(|name| stem == name.as_str()));
+            let wanted = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => records,
+                Some("log") => logs,
+                _ => false,
+            };
+            is_job && wanted && fs::remove_file(path).is_ok()
+        })
+        .collect();
+    removed.sort();
+    fs::remove_dir(dir()).ok();
+    removed
+}
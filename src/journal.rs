@@ -0,0 +1,245 @@
+// Every run is appended to a journal under target/, one JSON object per line,
+// so `--history` can show what was run and when, and `--compare` how long
+// each of its commands took compared to an earlier run.
+
+use duration;
+use git;
+use serde_json;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    timestamp: String,
+    command: String,
+    args: Vec<String>,
+    // In seconds.
+    duration: f64,
+    status: i32,
+    // The command in the chain that failed, for --retry-failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    failed: Option<String>,
+    // The commit the run was made at, for --compare.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git_sha: Option<String>,
+    // How long each command in the chain took.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    commands: Vec<Timing>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Timing {
+    pub name: String,
+    // In seconds.
+    pub duration: f64,
+    pub status: i32,
+    // The CPU time its processes used in seconds, and the most memory one of
+    // them used in bytes, when they're known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory: Option<u64>,
+}
+
+// A run of `cargo cmd` to record.
+pub struct Run<'a> {
+    pub command: &'a str,
+    pub args: &'a [String],
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub status: i32,
+    pub failed: Option<&'a str>,
+    pub timings: Vec<Timing>,
+}
+
+// What --retry-failed runs again: the command with the arguments it was given
+// last time, starting from the one in its chain that failed.
+pub struct Retry {
+    pub command: String,
+    pub args: Vec<String>,
+    pub from: Option<String>,
+}
+
+fn path(dir: &Path) -> PathBuf {
+    dir.join("target/cargo-cmd/journal.jsonl")
+}
+
+// The journal is only a record, so failing to write it is ignored.
+pub fn record(dir: &Path, run: Run) {
+    let entry = Entry {
+        timestamp: duration::timestamp(run.started),
+        command: run.command.to_string(),
+        args: run.args.to_vec(),
+        duration: run.duration.as_secs_f64(),
+        status: run.status,
+        failed: run.failed.map(str::to_string),
+        git_sha: git::info("sha"),
+        commands: run.timings,
+    };
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(_) => return,
+    };
+    let path = path(dir);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        writeln!(file, "{}", line).ok();
+    }
+}
+
+// Lines cut short by an interrupted write are skipped.
+fn entries(dir: &Path) -> Option<Vec<Entry>> {
+    let contents = fs::read_to_string(path(dir)).ok()?;
+    Some(
+        contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect(),
+    )
+}
+
+// The last run if it failed, or `None` if it succeeded.
+pub fn last_failure(dir: &Path) -> Result<Option<Retry>, String> {
+    let entry = entries(dir)
+        .and_then(|mut entries| entries.pop())
+        .ok_or("No runs recorded yet, there's nothing to retry")?;
+    if entry.status == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Retry {
+        command: entry.command,
+        args: entry.args,
+        from: entry.failed,
+    }))
+}
+
+// Print the recorded runs, oldest first.
+pub fn show(dir: &Path) {
+    let entries = match entries(dir) {
+        Some(entries) => entries,
+        None => {
+            println!("No runs recorded yet");
+            return;
+        }
+    };
+    for entry in entries {
+        let mut invocation = vec![entry.command];
+        invocation.extend(entry.args);
+        println!(
+            "{}  {:>8}  exit {:<3}  {}",
+            entry.timestamp,
+            duration::format(Duration::from_secs_f64(entry.duration)),
+            entry.status,
+            invocation.join(" ")
+        );
+    }
+}
+
+fn seconds(duration: f64) -> String {
+    duration::format(Duration::from_secs_f64(duration))
+}
+
+fn change(before: f64, after: f64) -> String {
+    if before > 0.0 {
+        format!("{:+.0}%", (after - before) / before * 100.0)
+    } else {
+        String::new()
+    }
+}
+
+fn describe(entry: &Entry) -> String {
+    match entry.git_sha {
+        Some(ref sha) => format!("{} ({})", entry.timestamp, &sha[..sha.len().min(7)]),
+        None => entry.timestamp.clone(),
+    }
+}
+
+// Show how long each command of the last run of `command`, or of the last run
+// if it's not given, took compared to the run of it before, or to the last
+// one made at the commit `rev`.
+pub fn compare(dir: &Path, command: Option<&str>, rev: Option<&str>) -> Result<(), String> {
+    let mut entries = entries(dir).unwrap_or_default();
+    let command = match command {
+        Some(command) => command.to_string(),
+        None => entries
+            .last()
+            .map(|entry| entry.command.clone())
+            .ok_or("No runs recorded yet, there's nothing to compare")?,
+    };
+    entries.retain(|entry| entry.command == command);
+    let after = entries
+        .pop()
+        .ok_or(format!("No runs of `{}` recorded yet", command))?;
+    let before = match rev {
+        Some(rev) => {
+            let sha = git::resolve(rev).ok_or(format!("Could not find the commit {}", rev))?;
+            entries
+                .into_iter()
+                .rev()
+                .find(|entry| entry.git_sha.as_ref() == Some(&sha))
+                .ok_or(format!("No runs of `{}` recorded at {}", command, rev))?
+        }
+        None => entries.pop().ok_or(format!(
+            "Only one run of `{}` recorded, there's nothing to compare it to",
+            command
+        ))?,
+    };
+
+    println!(
+        "`{}` at {} compared to {}",
+        command,
+        describe(&after),
+        describe(&before)
+    );
+    let mut names: Vec<&String> = before.commands.iter().map(|timing| &timing.name).collect();
+    for timing in &after.commands {
+        if !names.contains(&&timing.name) {
+            names.push(&timing.name);
+        }
+    }
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let duration_of = |entry: &Entry, name: &str| {
+        entry
+            .commands
+            .iter()
+            .find(|timing| timing.name == name)
+            .map(|timing| timing.duration)
+    };
+    let row = |name: &str, before: Option<f64>, after: Option<f64>| {
+        let change = match (before, after) {
+            (Some(before), Some(after)) => change(before, after),
+            _ => String::new(),
+        };
+        println!(
+            "  {:width$}  {:>8}  {:>8}  {}",
+            name,
+            before.map(seconds).unwrap_or_else(|| "-".to_string()),
+            after.map(seconds).unwrap_or_else(|| "-".to_string()),
+            change,
+            width = width
+        );
+    };
+    println!(
+        "  {:width$}  {:>8}  {:>8}",
+        "",
+        "before",
+        "after",
+        width = width
+    );
+    for name in names {
+        row(name, duration_of(&before, name), duration_of(&after, name));
+    }
+    row("total", Some(before.duration), Some(after.duration));
+    Ok(())
+}
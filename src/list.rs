@@ -0,0 +1,145 @@
+// The commands for --list, grouped by namespace, the part of their name
+// before a `:`, with their descriptions and the files they come from, and
+// the ones --search finds. `--format plain` lists just the names and
+// `--format json` everything about them, for editors and scripts.
+
+use command::{self, Command};
+use explain;
+use manifest::Origin;
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    // Where the command is defined, as `path:line`.
+    source: Option<String>,
+    tags: &'a [String],
+    // The pre and post commands that run with it.
+    hooks: Vec<String>,
+}
+
+fn namespace(name: &str) -> &str {
+    name.split_once(':').map_or("", |(namespace, _)| namespace)
+}
+
+fn sorted(commands: &HashMap<String, Command>) -> Vec<&String> {
+    let mut names: Vec<&String> = commands.keys().collect();
+    names.sort();
+    names
+}
+
+fn print_json(
+    commands: &HashMap<String, Command>,
+    origins: &HashMap<String, Vec<Origin>>,
+) -> Result<(), String> {
+    let entries: Vec<Entry> = sorted(commands)
+        .into_iter()
+        .map(|name| {
+            let options = &commands[name].options;
+            Entry {
+                name,
+                description: options.description.as_deref(),
+                source: origins
+                    .get(name)
+                    .and_then(|origins| origins.last())
+                    .map(Origin::to_string),
+                tags: &options.tags,
+                hooks: ["pre", "post"]
+                    .iter()
+                    .map(|phase| format!("{}{}", phase, name))
+                    .filter(|hook| !command::steps(commands, hook).is_empty())
+                    .collect(),
+            }
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries).or(Err("Could not list the commands"))?;
+    println!("{}", json);
+    Ok(())
+}
+
+pub fn print(
+    commands: &HashMap<String, Command>,
+    origins: &HashMap<String, Vec<Origin>>,
+    format: &str,
+) -> Result<(), String> {
+    match format {
+        "plain" => {
+            for name in sorted(commands) {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        "json" => print_json(commands, origins),
+        _ => {
+            print_table(commands, origins);
+            Ok(())
+        }
+    }
+}
+
+fn print_table(commands: &HashMap<String, Command>, origins: &HashMap<String, Vec<Origin>>) {
+    let mut namespaces: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for name in commands.keys() {
+        namespaces.entry(namespace(name)).or_default().push(name);
+    }
+    let width = commands.keys().map(String::len).max().unwrap_or(0);
+
+    for (index, (namespace, names)) in namespaces.iter_mut().enumerate() {
+        names.sort();
+        if index > 0 {
+            println!();
+        }
+        if !namespace.is_empty() {
+            println!("{}:", namespace);
+        }
+        for name in names.iter() {
+            let mut details: Vec<String> = vec![];
+            details.extend(commands[*name].options.description.clone());
+            details.extend(
+                origins
+                    .get(*name)
+                    .map(|paths| format!("({})", explain::origin(paths))),
+            );
+            println!("  {:width$}  {}", name, details.join(" "), width = width);
+        }
+    }
+}
+
+// Commands whose name, description or command contains `pattern`, ignoring
+// case. Returns whether there were any.
+pub fn search(
+    pattern: &str,
+    commands: &HashMap<String, Command>,
+    origins: &HashMap<String, Vec<Origin>>,
+) -> bool {
+    let pattern = pattern.to_lowercase();
+    let mut names: Vec<&String> = commands
+        .iter()
+        .filter(|(name, command)| {
+            [
+                Some(&name[..]),
+                command.options.description.as_deref(),
+                Some(&command.cmd),
+            ]
+            .iter()
+            .flatten()
+            .any(|text| text.to_lowercase().contains(&pattern))
+        })
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+
+    for name in &names {
+        let command = &commands[*name];
+        println!("{}: {}", name, command.cmd);
+        if let Some(ref description) = command.options.description {
+            println!("    {}", description);
+        }
+        if let Some(paths) = origins.get(*name) {
+            println!("    {}", explain::origin(paths));
+        }
+    }
+    !names.is_empty()
+}
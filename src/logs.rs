@@ -0,0 +1,248 @@
+// Log files for the output of services, with the time each line was written
+// in front of it, started over once they get too big or too old when
+// `log_rotate` is set, with the last `log_keep` of them kept next to them as
+// `<file>.1`, `<file>.2` and so on. --logs shows them, and the logs of
+// background commands.
+
+use command::Command;
+use duration;
+use jobs;
+use size;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_KEEP: usize = 5;
+// The length of the time in front of each line, see `duration::timestamp`.
+const TIMESTAMP_LENGTH: usize = 20;
+
+enum Rotate {
+    Size(u64),
+    Every(Duration),
+}
+
+pub struct Log {
+    path: String,
+    file: File,
+    written: u64,
+    opened: Instant,
+    rotate: Option<Rotate>,
+    keep: usize,
+}
+
+// Sizes are given with a unit of bytes like `10MB` or `512K`, times with a
+// unit of time like `30m` or `24h`.
+fn parse_rotate(value: &str) -> Result<Rotate, String> {
+    let value = value.trim();
+    if value.ends_with(|c: char| c.is_ascii_uppercase() || c == 'b') {
+        size::parse(value).map(Rotate::Size)
+    } else {
+        duration::parse(value).map(Rotate::Every)
+    }
+}
+
+fn open(path: &str, append: bool) -> Result<File, String> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .or(Err(format!("Could not open {} for writing", path)))
+}
+
+impl Log {
+    pub fn open(
+        path: &str,
+        append: bool,
+        rotate: Option<&String>,
+        keep: Option<usize>,
+    ) -> Result<Log, String> {
+        let file = open(path, append)?;
+        Ok(Log {
+            path: path.to_string(),
+            written: file.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            file,
+            opened: Instant::now(),
+            rotate: rotate.map(|rotate| parse_rotate(rotate)).transpose()?,
+            keep: keep.unwrap_or(DEFAULT_KEEP),
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        let line = format!("{} {}", duration::timestamp(SystemTime::now()), line);
+        let length = line.len() as u64 + 1;
+        let full = match self.rotate {
+            Some(Rotate::Size(limit)) => self.written > 0 && self.written + length > limit,
+            Some(Rotate::Every(every)) => self.opened.elapsed() >= every,
+            None => false,
+        };
+        if full {
+            self.start_over();
+        }
+        if writeln!(self.file, "{}", line).is_ok() {
+            self.written += length;
+        }
+    }
+
+    // Move the file out of the way behind the ones kept before it, and open a
+    // new one in its place.
+    fn start_over(&mut self) {
+        let kept = |index: usize| format!("{}.{}", self.path, index);
+        if self.keep == 0 {
+            fs::remove_file(&self.path).ok();
+        } else {
+            fs::remove_file(kept(self.keep)).ok();
+            for index in (1..self.keep).rev() {
+                fs::rename(kept(index), kept(index + 1)).ok();
+            }
+            fs::rename(&self.path, kept(1)).ok();
+        }
+        if let Ok(file) = open(&self.path, false) {
+            self.file = file;
+        }
+        self.written = 0;
+        self.opened = Instant::now();
+    }
+}
+
+// The logs of the background command or service `name`. Services can also be
+// given as `<group>.<service>`.
+pub fn paths(
+    name: &str,
+    services: &HashMap<String, BTreeMap<String, Command>>,
+) -> Result<Vec<String>, String> {
+    if let Some(log) = jobs::log(name) {
+        return Ok(vec![log]);
+    }
+    let mut groups: Vec<&String> = services.keys().collect();
+    groups.sort();
+    let service = groups.iter().find_map(|group| {
+        let service = match name.strip_prefix(&format!("{}.", group)) {
+            Some(service) => service,
+            None => name,
+        };
+        services[*group].get(service)
+    });
+    let options = match service {
+        Some(service) => &service.options,
+        None => {
+            return Err(format!(
+                "No logs for \"{}\", it isn't a background command or a service",
+                name
+            ))
+        }
+    };
+    let mut paths: Vec<String> = options
+        .stdout_file
+        .iter()
+        .chain(options.stderr_file.iter())
+        .cloned()
+        .collect();
+    paths.dedup();
+    if paths.is_empty() {
+        return Err(format!(
+            "Service \"{}\" shows its output instead of writing it to a log, see `stdout_file`",
+            name
+        ));
+    }
+    Ok(paths)
+}
+
+// The log at `path` with the ones kept from before it, oldest first.
+fn files(path: &str) -> Vec<String> {
+    let mut files: Vec<String> = (1..)
+        .map(|index| format!("{}.{}", path, index))
+        .take_while(|file| Path::new(file).exists())
+        .collect();
+    files.reverse();
+    files.push(path.to_string());
+    files
+}
+
+fn print_from(path: &str, position: u64) -> u64 {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return position,
+    };
+    let mut contents = vec![];
+    file.seek(SeekFrom::Start(position)).ok();
+    file.read_to_end(&mut contents).ok();
+    io::stdout().write_all(&contents).ok();
+    io::stdout().flush().ok();
+    position + contents.len() as u64
+}
+
+// Print the lines of the log at `path` written in the last `since`. Lines
+// without a time in front of them, like the output of background commands, go
+// by the line before them, or by when the file was last written to.
+fn print_since(path: &str, since: Duration) {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+    let cutoff = SystemTime::now().checked_sub(since).unwrap_or(UNIX_EPOCH);
+    let mut recent = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_or(true, |modified| modified >= cutoff);
+    let mut stdout = io::stdout().lock();
+    for line in contents.lines() {
+        if let Some(time) = line
+            .get(..TIMESTAMP_LENGTH)
+            .and_then(duration::parse_timestamp)
+        {
+            recent = time >= cutoff;
+        }
+        if recent {
+            writeln!(stdout, "{}", line).ok();
+        }
+    }
+    stdout.flush().ok();
+}
+
+// Print the logs, only the lines written in the last `since` if it's given.
+// With `follow` this keeps printing what's added to them until it's
+// interrupted.
+pub fn show(paths: &[String], since: Option<&String>, follow: bool) -> Result<(), String> {
+    let since = since.map(|since| duration::parse(since)).transpose()?;
+    let mut positions = vec![];
+    for path in paths {
+        if !Path::new(path).exists() {
+            return Err(format!("There is no log at {} yet", path));
+        }
+        for file in files(path) {
+            match since {
+                Some(since) => print_since(&file, since),
+                None => {
+                    print_from(&file, 0);
+                }
+            }
+        }
+        positions.push(
+            fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0),
+        );
+    }
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        thread::sleep(Duration::from_millis(250));
+        for (path, position) in paths.iter().zip(positions.iter_mut()) {
+            let length = fs::metadata(path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            // It was started over.
+            if length < *position {
+                *position = 0;
+            }
+            if length > *position {
+                *position = print_from(path, *position);
+            }
+        }
+    }
+}
@@ -1,121 +1,1048 @@
 #[macro_use]
 extern crate serde;
 extern crate clap;
+extern crate ctrlc;
+extern crate glob;
+#[cfg(unix)]
+extern crate libc;
+extern crate rhai;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate shell_words;
 extern crate structopt;
 extern crate subprocess;
 extern crate toml;
+extern crate toml_edit;
+extern crate ureq;
+#[cfg(windows)]
+extern crate winapi;
 
+mod aliases;
+mod build;
+mod builtins;
+mod cache;
+mod cargo_make;
+mod ci;
+mod clean;
+mod command;
+mod cycles;
+mod duration;
+mod env_file;
+mod exec;
+mod explain;
+mod export;
+mod git;
+mod interpolate;
+mod jobs;
+mod journal;
+mod list;
+mod logs;
+mod manifest;
+mod matrix;
+mod notify;
+mod package_json;
+mod params;
+mod plugins;
+mod process_tree;
+mod quote;
+mod requires;
+mod script;
+mod self_update;
+mod services;
+mod shell_aliases;
+mod size;
+mod suggest;
+mod target;
+mod term;
+mod trace;
+mod version;
+mod workflow;
+
+use command::Command;
+use exec::Invocation;
+use params::Param;
+use plugins::Plugins;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 use structopt::StructOpt;
-use subprocess::{Exec, ExitStatus};
+use subprocess::ExitStatus;
+use trace::Trace;
+
+const WILDCARD_HOOKS: [&str; 2] = ["pre*", "post*"];
+const SESSION_COMMANDS: [&str; 2] = ["setup", "teardown"];
+
+// Exit code used when --max-time stops the run, the same as timeout(1).
+const TIMED_OUT: i32 = 124;
+// Exit code used when the run is interrupted, the same as shells use.
+const INTERRUPTED: i32 = 130;
+// Exit codes for cargo-cmd's own errors, from the range sysexits.h uses so
+// they stand apart from what commands usually exit with.
+const CONFIG_ERROR: i32 = 64;
+const NOT_FOUND: i32 = 65;
+const COULD_NOT_START: i32 = 66;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "cargo-cmd", bin_name = "cargo")]
 enum Cli {
     #[structopt(name = "cmd")]
-    Cmd {
-        #[structopt(name = "command", index = 1)]
-        command: String,
-        #[structopt(multiple = true)]
-        rest: Vec<String>,
-    },
+    Cmd(Args),
 }
 
-#[derive(Deserialize, Debug)]
-struct Cargotoml {
-    package: Package,
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(after_help = "EXIT CODES:
+    The exit code of the command that failed, or:
+    64    Cargo.toml or a file with commands could not be read
+    65    The command was not found
+    66    A command could not be started
+    124   --max-time was exceeded
+    130   The run was interrupted
+    1     Any other error")]
+struct Args {
+    /// Run for CI: no prompts or colors, output of parallel runs kept apart and a JSON summary at
+    /// the end. On when the CI environment variable is set
+    #[structopt(long = "ci")]
+    ci: bool,
+    /// Stop running commands once <max-time> has passed, e.g. 90s or 30m
+    #[structopt(long = "max-time", parse(try_from_str = duration::parse))]
+    max_time: Option<Duration>,
+    /// Run the runs of every matrix one after the other, even if it's `parallel`
+    #[structopt(long = "force-sequential")]
+    force_sequential: bool,
+    /// Report the time and memory each command used
+    #[structopt(long = "report-usage")]
+    report_usage: bool,
+    /// Export a trace of the run to <trace>, a JSON file or an OTLP/HTTP endpoint URL
+    #[structopt(long = "trace")]
+    trace: Option<String>,
+    /// Only run the pre commands of <command>, can be combined with the other --only flags
+    #[structopt(long = "only-pre")]
+    only_pre: bool,
+    /// Only run <command> itself, without its pre and post commands
+    #[structopt(long = "only-main")]
+    only_main: bool,
+    /// Only run the post commands of <command>
+    #[structopt(long = "only-post")]
+    only_post: bool,
+    /// Start the chain of <command> at <from>, skipping the commands before it
+    #[structopt(long = "from")]
+    from: Option<String>,
+    /// Run the last run again if it failed, starting from the command that failed
+    #[structopt(long = "retry-failed", conflicts_with_all = &["from", "exec", "tag"])]
+    retry_failed: bool,
+    /// Leave <skip> out of the chain of <command>, can be given more than once
+    #[structopt(long = "skip", number_of_values = 1)]
+    skip: Vec<String>,
+    /// Only run the commands of the chain whose `required_features` are among <features>, separated by commas or spaces
+    #[structopt(long = "features")]
+    features: Option<String>,
+    /// Run <exec> as a command, with the environment commands get
+    #[structopt(long = "exec", conflicts_with = "interactive")]
+    exec: Option<String>,
+    /// With --exec, run <exec> in place of the command <as>, with its options and hooks
+    #[structopt(long = "as", name = "as", requires = "exec")]
+    as_name: Option<String>,
+    /// Explain where <command> and the commands that run with it come from, instead of running it
+    #[structopt(long = "explain")]
+    explain: bool,
+    /// Start a shell with the environment <command> would run with, instead of running it
+    #[structopt(long = "shell-env")]
+    shell_env: bool,
+    /// Add a `cargo <command>` alias to .cargo/config.toml for each command
+    #[structopt(long = "install-aliases")]
+    install_aliases: bool,
+    /// Remove the aliases added by --install-aliases
+    #[structopt(long = "remove-aliases")]
+    remove_aliases: bool,
+    /// Add a shell alias like `ccb` for `cargo cmd build` to the rc file of $SHELL for each command
+    #[structopt(long = "install-shell-aliases")]
+    install_shell_aliases: bool,
+    /// Remove the shell aliases added by --install-shell-aliases for this package
+    #[structopt(long = "remove-shell-aliases")]
+    remove_shell_aliases: bool,
+    /// Read commands to run from stdin until `exit`, reading the manifest once
+    #[structopt(short = "i", long = "interactive", conflicts_with = "shell-env")]
+    interactive: bool,
+    /// Write the commands to COMMANDS.md as a Markdown table, to .vscode/tasks.json as VS Code tasks or to .idea/runConfigurations as JetBrains run configurations
+    #[structopt(long = "export", possible_values = &["markdown", "vscode", "idea"])]
+    export: Option<String>,
+    /// List the commands by namespace, with their descriptions and the files they come from
+    #[structopt(long = "list")]
+    list: bool,
+    /// How --list shows the commands, a table by default
+    #[structopt(
+        long = "format",
+        possible_values = &["table", "plain", "json"],
+        requires = "list"
+    )]
+    format: Option<String>,
+    /// Find the commands whose name, description or command contains <search>
+    #[structopt(long = "search")]
+    search: Option<String>,
+    /// Run every command with <tag> in its `tags`, in alphabetical order
+    #[structopt(long = "tag", conflicts_with = "exec")]
+    tag: Option<String>,
+    /// Show the commands still running in the background
+    #[structopt(long = "jobs-list")]
+    jobs_list: bool,
+    /// Stop the background command <stop>
+    #[structopt(long = "stop")]
+    stop: Option<String>,
+    /// Check that the `cargo cmd` calls in the GitHub Actions workflow <verify-ci> run commands that exist
+    #[structopt(long = "verify-ci", parse(from_os_str))]
+    verify_ci: Option<PathBuf>,
+    /// Show the output of the background command or service <logs>
+    #[structopt(long = "logs")]
+    logs: Option<String>,
+    /// Keep showing the output --logs shows as it's written
+    #[structopt(long = "follow", requires = "logs")]
+    follow: bool,
+    /// Only show the lines --logs shows that were written in the last <since>, like 10m
+    #[structopt(long = "since", requires = "logs")]
+    since: Option<String>,
+    /// Show the runs recorded in target/cargo-cmd/journal.jsonl
+    #[structopt(long = "history")]
+    history: bool,
+    /// Show how long the commands of the last run took compared to the run before, or to the last run at the commit [compare], like HEAD~1
+    #[structopt(long = "compare", name = "compare")]
+    compare: Option<Option<String>>,
+    /// Remove what cargo-cmd keeps in target/cargo-cmd, or only the [clean] parts of it given
+    #[structopt(long = "clean", name = "clean", possible_values = clean::PARTS)]
+    clean: Option<Vec<String>>,
+    /// Install the latest version of cargo-cmd from crates.io
+    #[structopt(long = "self-update")]
+    self_update: bool,
+    /// With --self-update, only fail if a newer version is available
+    #[structopt(long = "check-only", requires = "self-update")]
+    check_only: bool,
+    /// Fail on keys in the command tables that cargo-cmd doesn't know
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Read the commands from the manifest even if they are cached
+    #[structopt(long = "no-cache")]
+    no_cache: bool,
+    /// Install missing cargo subcommands listed in `requires` without asking
+    #[structopt(long = "auto-install")]
+    auto_install: bool,
+    /// Pick the commands for <target>, and build for it when they run cargo
+    #[structopt(long = "target")]
+    target: Option<String>,
+    /// Pass <profile> on to the commands, and build with it for `needs_build` and `bin`
+    #[structopt(long = "profile")]
+    profile: Option<String>,
+    /// Run as if cargo-cmd was started in <dir> instead of the current directory
+    #[structopt(short = "C", long = "chdir", name = "dir", parse(from_os_str))]
+    chdir: Option<PathBuf>,
+    #[structopt(
+        name = "command",
+        index = 1,
+        required_unless_one = &[
+            "clean",
+            "exec",
+            "export",
+            "compare",
+            "history",
+            "install-aliases",
+            "install-shell-aliases",
+            "interactive",
+            "jobs-list",
+            "list",
+            "logs",
+            "remove-aliases",
+            "remove-shell-aliases",
+            "retry-failed",
+            "search",
+            "self-update",
+            "stop",
+            "tag",
+            "verify-ci",
+        ]
+    )]
+    command: Option<String>,
+    #[structopt(multiple = true)]
+    rest: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct Package {
-    metadata: Metadata,
+fn main() {
+    let matches = Cli::clap()
+        .global_setting(term::clap_setting())
+        .get_matches();
+    let Cli::Cmd(args) = Cli::from_clap(&matches);
+    if args.ci {
+        ci::enable();
+    }
+    if let Some(ref dir) = args.chdir {
+        unwrap_or_exit(env::set_current_dir(dir).or(Err(format!(
+            "Could not change to directory {}",
+            dir.display()
+        ))));
+    }
+    if args.history {
+        journal::show(Path::new("."));
+        return;
+    }
+    if let Some(ref parts) = args.clean {
+        unwrap_or_exit(clean::clean(parts));
+        return;
+    }
+    if let Some(ref rev) = args.compare {
+        unwrap_or_exit(journal::compare(
+            Path::new("."),
+            args.command.as_deref(),
+            rev.as_deref(),
+        ));
+        return;
+    }
+    if args.jobs_list {
+        jobs::list();
+        return;
+    }
+    if let Some(ref name) = args.stop {
+        unwrap_or_exit(jobs::stop(name));
+        return;
+    }
+    if let Some(ref name) = args.logs {
+        let settings = unwrap_or_exit_with(manifest::settings(Path::new(".")), CONFIG_ERROR);
+        let paths = unwrap_or_exit(logs::paths(name, &settings.services));
+        unwrap_or_exit(logs::show(&paths, args.since.as_ref(), args.follow));
+        return;
+    }
+    if args.self_update {
+        unwrap_or_exit(self_update::run(args.check_only));
+        return;
+    }
+    if args.remove_aliases {
+        unwrap_or_exit(aliases::remove());
+        return;
+    }
+    if args.install_aliases {
+        let cargo_commands = unwrap_or_exit_with(
+            manifest::load_commands(Path::new("."), args.strict, !args.no_cache),
+            CONFIG_ERROR,
+        );
+        unwrap_or_exit(aliases::install(&cargo_commands));
+        return;
+    }
+    if args.remove_shell_aliases || args.install_shell_aliases {
+        let package = unwrap_or_exit(
+            manifest::package_name(Path::new("."))
+                .ok_or_else(|| "Could not find the package name in Cargo.toml".to_string()),
+        );
+        if args.remove_shell_aliases {
+            unwrap_or_exit(shell_aliases::remove(&package));
+        } else {
+            let cargo_commands = unwrap_or_exit_with(
+                manifest::load_commands(Path::new("."), args.strict, !args.no_cache),
+                CONFIG_ERROR,
+            );
+            unwrap_or_exit(shell_aliases::install(&cargo_commands, &package));
+        }
+        return;
+    }
+    if let (Some(command), false, None, None) =
+        (&args.command, args.interactive, &args.exec, &args.tag)
+    {
+        if builtins::is_builtin(command) {
+            unwrap_or_exit(builtins::run(command, &args.rest));
+            return;
+        }
+    }
+    let mut cargo_commands = unwrap_or_exit_with(
+        manifest::load_commands(Path::new("."), args.strict, !args.no_cache),
+        CONFIG_ERROR,
+    );
+    let settings = unwrap_or_exit_with(manifest::settings(Path::new(".")), CONFIG_ERROR);
+    if let Some(ref path) = args.verify_ci {
+        unwrap_or_exit(workflow::verify(path, &cargo_commands, &settings.services));
+        return;
+    }
+    if let Some(ref format) = args.export {
+        unwrap_or_exit(export::run(format, &cargo_commands));
+        return;
+    }
+    if args.list {
+        let origins = unwrap_or_exit_with(
+            manifest::load_origins(Path::new("."), args.strict),
+            CONFIG_ERROR,
+        );
+        unwrap_or_exit(list::print(
+            &cargo_commands,
+            &origins,
+            args.format.as_deref().unwrap_or("table"),
+        ));
+        return;
+    }
+    if let Some(ref pattern) = args.search {
+        let origins = unwrap_or_exit_with(
+            manifest::load_origins(Path::new("."), args.strict),
+            CONFIG_ERROR,
+        );
+        if !list::search(pattern, &cargo_commands, &origins) {
+            eprintln!("No commands match \"{}\"", pattern);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.interactive {
+        interactive(&args, &cargo_commands);
+        return;
+    }
+    if let Some(ref tag) = args.tag {
+        let mut names: Vec<String> = cargo_commands
+            .iter()
+            .filter(|(_, command)| command.options.tags.contains(tag))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        if names.is_empty() {
+            unwrap_or_exit::<()>(Err(format!("No commands are tagged \"{}\"", tag)));
+        }
+        // Every positional argument is passed on to each command, which run
+        // with their hooks and stop at the first one that fails.
+        let rest: Vec<String> = args.command.iter().chain(&args.rest).cloned().collect();
+        for name in names {
+            term::status(&format!("> cargo cmd {}", name));
+            let exit_code = unwrap_or_exit(run(&args, cargo_commands.clone(), &name, &rest));
+            if exit_code != 0 {
+                process::exit(exit_code);
+            }
+        }
+        return;
+    }
+    if let Some(ref cmd) = args.exec {
+        // The ad-hoc command takes the place of the one it runs as, keeping
+        // its options and hooks. Every positional argument is passed on.
+        let name = args.as_name.clone().unwrap_or_else(|| "exec".to_string());
+        let options = cargo_commands
+            .remove(&name)
+            .map(|command| command.options)
+            .unwrap_or_default();
+        let command = Command {
+            cmd: cmd.to_string(),
+            options: command::Options {
+                script: false,
+                ..options
+            },
+        };
+        cargo_commands.insert(name.to_string(), command);
+        let rest: Vec<String> = args.command.iter().chain(&args.rest).cloned().collect();
+        process::exit(unwrap_or_exit(run(&args, cargo_commands, &name, &rest)));
+    }
+    if args.retry_failed {
+        let retry = match unwrap_or_exit(journal::last_failure(Path::new("."))) {
+            Some(retry) => retry,
+            None => {
+                println!("The last run succeeded, there's nothing to retry");
+                return;
+            }
+        };
+        // Session commands run again anyway.
+        let from = retry
+            .from
+            .filter(|from| !SESSION_COMMANDS.contains(&&from[..]));
+        let mut retried = vec![retry.command.clone()];
+        retried.extend(retry.args.iter().cloned());
+        let retried = format!("Retrying `cargo cmd {}`", quote::join(&retried));
+        match from {
+            Some(ref from) => term::status(&format!("{} from {}", retried, from)),
+            None => term::status(&retried),
+        }
+        let args = Args {
+            from,
+            ..args.clone()
+        };
+        process::exit(unwrap_or_exit(run(
+            &args,
+            cargo_commands,
+            &retry.command,
+            &retry.args,
+        )));
+    }
+    let command = args.command.clone().unwrap_or_default();
+    if let Some(services) = settings.services.get(&command) {
+        let invocation = Invocation {
+            env: git::env(),
+            values: HashMap::new(),
+            placeholders: manifest::metadata_values(Path::new(".")),
+            rest: vec![],
+            deadline: None,
+            report_usage: false,
+        };
+        let exit_code = unwrap_or_exit(services::run(services, &invocation));
+        process::exit(if process_tree::interrupted() {
+            INTERRUPTED
+        } else {
+            exit_code
+        });
+    }
+    if command::steps(&cargo_commands, &command).is_empty() && !settings.fallback_to_cargo {
+        let error = suggest::not_found(&cargo_commands, &command);
+        unwrap_or_exit_with::<()>(Err(error), NOT_FOUND);
+    }
+    process::exit(unwrap_or_exit(run(
+        &args,
+        cargo_commands,
+        &command,
+        &args.rest,
+    )));
 }
 
-#[derive(Deserialize, Debug)]
-struct Metadata {
-    commands: HashMap<String, String>,
+// Run `command` along with its hooks and session commands. Returns the exit
+// code cargo-cmd should exit with.
+fn run(
+    args: &Args,
+    mut cargo_commands: HashMap<String, Command>,
+    command: &str,
+    rest: &[String],
+) -> Result<i32, String> {
+    let settings = manifest::settings(Path::new("."))?;
+    let package = manifest::package_name(Path::new(".")).unwrap_or_default();
+    let plugins = Plugins::new(&settings.plugins, &package);
+    plugins.pre_resolution(command, rest)?;
+    let mut env = git::env();
+    if ci::enabled() {
+        env.extend(ci::env());
+    }
+    if let Some(ref target) = args.target {
+        env.push(("CARGO_BUILD_TARGET".to_string(), target.to_string()));
+    }
+    // Commands with a variant for the target are replaced by it.
+    let mut target = None;
+    let mut variants = vec![];
+    if cargo_commands
+        .values()
+        .any(|command| !command.options.target.is_empty())
+    {
+        let resolved = target::resolve(args.target.as_deref())?;
+        for (name, command) in cargo_commands.iter_mut() {
+            if let Some(variant) = command.options.target.remove(&resolved) {
+                *command = variant;
+                variants.push(name.to_string());
+            }
+        }
+        target = Some(resolved);
+    }
+    // The target and profile asked for are passed on to the commands and fill
+    // in their `${target}` and `${profile}` placeholders.
+    let target = target
+        .or_else(|| args.target.clone())
+        .or_else(|| env::var("CARGO_BUILD_TARGET").ok());
+    let mut placeholders = HashMap::new();
+    if let Some(ref target) = target {
+        env.push(("CARGO_CMD_TARGET".to_string(), target.to_string()));
+        placeholders.insert("target".to_string(), target.to_string());
+    }
+    if let Some(ref profile) = args.profile {
+        env.push(("CARGO_CMD_PROFILE".to_string(), profile.to_string()));
+        placeholders.insert("profile".to_string(), profile.to_string());
+    }
+    if args.force_sequential {
+        for command in cargo_commands.values_mut() {
+            command.options.parallel = Some(false);
+        }
+    }
+    if args.shell_env {
+        spawn_shell(&mut cargo_commands, command, rest, env, &placeholders);
+    }
+    if command::steps(&cargo_commands, command).is_empty() && settings.fallback_to_cargo {
+        let cargo = Command::from(format!("cargo {}", command));
+        cargo_commands.insert(command.to_string(), cargo);
+    }
+    cycles::check(&cargo_commands, command)?;
+    let mut commands = get_commands(&mut cargo_commands, command)?;
+    // The --only flags split the chain into phases, so they can run in
+    // separate CI jobs. Session commands are kept for each of them.
+    if args.only_pre || args.only_main || args.only_post {
+        // The command itself can be a list of steps.
+        let is_main = |c: &(String, Command)| command::base_name(&c.0, &c.1) == command;
+        let first = commands.iter().position(is_main).unwrap_or(0);
+        let last = commands.iter().rposition(is_main).unwrap_or(first);
+        commands = commands
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| match (index.cmp(&first), index.cmp(&last)) {
+                (Ordering::Less, _) => args.only_pre,
+                (_, Ordering::Greater) => args.only_post,
+                _ => args.only_main,
+            })
+            .map(|(_, command)| command)
+            .collect();
+    }
+    // --from and --skip pick up a chain where it failed. The steps of a list
+    // can be named one by one or all together by the name of the list.
+    let named = |c: &(String, Command), wanted: &str| {
+        c.0 == wanted || command::base_name(&c.0, &c.1) == wanted
+    };
+    for wanted in args.from.iter().chain(&args.skip) {
+        if !commands.iter().any(|c| named(c, wanted)) {
+            return Err(format!(
+                "\"{}\" doesn't run as part of `cargo cmd {}`",
+                wanted, command
+            ));
+        }
+    }
+    if let Some(ref from) = args.from {
+        let start = commands.iter().position(|c| named(c, from)).unwrap_or(0);
+        commands.drain(..start);
+    }
+    commands.retain(|c| !args.skip.iter().any(|skip| named(c, skip)));
+    // With --features, the commands that need other features are left out.
+    if let Some(ref features) = args.features {
+        let selected: Vec<&str> = features
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|feature| !feature.is_empty())
+            .collect();
+        commands.retain(|c| {
+            let missing: Vec<&str> =
+                c.1.options
+                    .required_features
+                    .iter()
+                    .map(|feature| &feature[..])
+                    .filter(|feature| !selected.contains(feature))
+                    .collect();
+            if !missing.is_empty() {
+                term::status(&format!(
+                    "Skipping `{}`, it needs the features {}",
+                    c.0,
+                    missing.join(", ")
+                ));
+            }
+            missing.is_empty()
+        });
+    }
+    // Session commands run once around everything else, and the teardown
+    // runs even if something before it failed.
+    let mut teardown = None;
+    if !SESSION_COMMANDS.contains(&command) {
+        if let Some(setup) = cargo_commands.remove(SESSION_COMMANDS[0]) {
+            commands.insert(0, (SESSION_COMMANDS[0].to_string(), setup));
+        }
+        teardown = cargo_commands
+            .remove(SESSION_COMMANDS[1])
+            .map(|teardown| (SESSION_COMMANDS[1].to_string(), teardown));
+    }
+    // Only the commands that run are filled in, as functions like `${git(...)}`
+    // run something themselves.
+    for command in commands.iter_mut().chain(teardown.iter_mut()) {
+        fill_in(&mut command.1, &placeholders);
+    }
+    if args.explain {
+        let origins = manifest::load_origins(Path::new("."), args.strict)?;
+        let chain: Vec<_> = commands.iter().chain(teardown.iter()).collect();
+        explain::print(command, &chain, &origins, target.as_deref(), &variants);
+        return Ok(0);
+    }
+    let is_multiple_commands = commands.len() + teardown.iter().count() > 1;
+    requires::check(
+        commands
+            .iter()
+            .chain(teardown.iter())
+            .map(|command| &command.1),
+        args.auto_install,
+    )?;
+    build::ensure(
+        commands
+            .iter_mut()
+            .chain(teardown.iter_mut())
+            .map(|command| &mut command.1),
+        &env,
+        args.profile.as_deref(),
+    )?;
+    // Named params belong to the whole invocation, so hooks can use the
+    // values passed for the main command too.
+    let mut declared: Vec<Param> = vec![];
+    for param in commands
+        .iter()
+        .chain(teardown.iter())
+        .flat_map(|command| &command.1.options.params)
+    {
+        if !declared.iter().any(|d| d.name == param.name) {
+            declared.push(param.clone());
+        }
+    }
+    let (values, params_rest) = params::resolve(command, &declared, rest)?;
+    process_tree::install_interrupt_handler();
+    let mut invocation = Invocation {
+        env,
+        values,
+        placeholders: manifest::metadata_values(Path::new(".")),
+        rest: params_rest,
+        deadline: args.max_time.map(|max_time| Instant::now() + max_time),
+        report_usage: args.report_usage,
+    };
+    let trace = args
+        .trace
+        .as_ref()
+        .map(|destination| Trace::new(destination, &package, command));
+    let started = (SystemTime::now(), Instant::now());
+
+    let timings = Mutex::new(vec![]);
+    let (mut exit_code, failed) = run_commands(
+        &commands,
+        &mut invocation,
+        is_multiple_commands,
+        &trace,
+        &plugins,
+        &timings,
+    );
+    if let Some(teardown) = teardown {
+        invocation.deadline = None;
+        let (teardown_exit_code, _) = run_commands(
+            &[teardown],
+            &mut invocation,
+            is_multiple_commands,
+            &trace,
+            &plugins,
+            &timings,
+        );
+        if exit_code == 0 {
+            exit_code = teardown_exit_code;
+        }
+    }
+    // A trace that can't be exported doesn't fail the run.
+    if let Some(Err(error_msg)) = trace.map(|trace| trace.export(exit_code)) {
+        eprintln!("warning: {}", error_msg);
+    }
+    if let Some(ref notifications) = settings.notifications {
+        if let Err(error_msg) =
+            notifications.send(&package, command, started.1.elapsed(), exit_code)
+        {
+            eprintln!("warning: {}", error_msg);
+        }
+    }
+    if let Err(error_msg) = plugins.post_run(command, rest, started.1.elapsed(), exit_code) {
+        eprintln!("warning: {}", error_msg);
+    }
+    let timings = timings.into_inner().unwrap_or_default();
+    journal::record(
+        Path::new("."),
+        journal::Run {
+            command,
+            args: rest,
+            started: started.0,
+            duration: started.1.elapsed(),
+            status: exit_code,
+            failed: failed.as_deref(),
+            timings: timings.clone(),
+        },
+    );
+    if ci::enabled() {
+        ci::summary(
+            &package,
+            command,
+            rest,
+            started.1.elapsed(),
+            exit_code,
+            timings,
+        );
+    }
+    Ok(exit_code)
 }
 
-fn main() {
-    let cli = Cli::from_args();
-    let (command, rest) = match cli {
-        Cli::Cmd { command, rest } => (command, rest),
+// Read commands with their arguments from stdin and run them one at a time,
+// without reading the manifest again. Commands named like the REPL's own
+// `help`, `history` and `exit` take precedence over them.
+fn interactive(args: &Args, cargo_commands: &HashMap<String, Command>) {
+    let mut names: Vec<&str> = cargo_commands.keys().map(|name| &name[..]).collect();
+    names.sort_unstable();
+    println!("Commands: {}", names.join(", "));
+    println!("Enter a command to run it, `help` to list the commands, `history` to show past runs or `exit` to quit");
+    // Ctrl-C stops the running command rather than cargo-cmd.
+    process_tree::install_interrupt_handler();
+    loop {
+        print!("cargo cmd> ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return;
+        }
+        let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        let (command, rest) = match words.split_first() {
+            Some(words) => words,
+            None => continue,
+        };
+        let result = match &command[..] {
+            _ if cargo_commands.contains_key(command) => {
+                run(args, cargo_commands.clone(), command, rest)
+            }
+            _ if builtins::is_builtin(command) => builtins::run(command, rest).map(|_| 0),
+            "help" => {
+                println!("Commands: {}", names.join(", "));
+                Ok(0)
+            }
+            "history" => {
+                journal::show(Path::new("."));
+                Ok(0)
+            }
+            "exit" | "quit" => return,
+            _ => Err(suggest::not_found(cargo_commands, command)),
+        };
+        match result {
+            Ok(0) => {}
+            Ok(exit_code) => eprintln!("Exited with code {}", exit_code),
+            Err(error_msg) => eprintln!("error: {}", error_msg),
+        }
+        process_tree::reset_interrupted();
+    }
+}
+
+fn spawn_shell(
+    cargo_commands: &mut HashMap<String, Command>,
+    name: &str,
+    rest: &[String],
+    env: Vec<(String, String)>,
+    placeholders: &HashMap<String, String>,
+) -> ! {
+    let mut command = match cargo_commands.remove(name) {
+        Some(command) => command,
+        None => unwrap_or_exit(Err(suggest::not_found(cargo_commands, name))),
+    };
+    fill_in(&mut command, placeholders);
+    let (values, _) = unwrap_or_exit(params::resolve(name, &command.options.params, rest));
+    let invocation = Invocation {
+        env,
+        values,
+        placeholders: manifest::metadata_values(Path::new(".")),
+        rest: vec![],
+        deadline: None,
+        report_usage: false,
     };
-    let commands = unwrap_or_exit(get_commands(&command));
-    let is_multiple_commands = commands.len() > 1;
+    match unwrap_or_exit(exec::spawn_shell(name, &command, &invocation)) {
+        ExitStatus::Exited(exit_code) => process::exit(exit_code as i32),
+        _ => process::exit(1),
+    }
+}
 
-    for (index, command) in commands.iter().enumerate() {
-        if is_multiple_commands {
-            println!("\n[{}]", &command.0);
+// Fill in the placeholders of `command` known before it runs.
+fn fill_in(command: &mut Command, placeholders: &HashMap<String, String>) {
+    if command.options.script || command.options.interpolate == Some(false) {
+        return;
+    }
+    // `${features}` turns the command's `required_features` into the flag for
+    // cargo. Params and matrix keys of the command with the same names as
+    // these placeholders are filled in later instead.
+    let mut placeholders = placeholders.clone();
+    let features = &command.options.required_features;
+    let flag = if features.is_empty() {
+        String::new()
+    } else {
+        format!("--features {}", features.join(","))
+    };
+    placeholders.insert("features".to_string(), flag);
+    let options = &command.options;
+    placeholders.retain(|name, _| {
+        !options.params.iter().any(|param| &param.name == name)
+            && !options.matrix.contains_key(name)
+    });
+    command.cmd = interpolate::interpolate(&command.cmd, &placeholders);
+}
+
+// Run the commands one after the other, stopping at the first one that fails.
+// Commands with a matrix run once for each combination, all at the same time
+// if they're `parallel`. Returns the exit code cargo-cmd should exit with, and
+// the command that failed.
+fn run_commands(
+    commands: &[(String, Command)],
+    invocation: &mut Invocation,
+    labels: bool,
+    trace: &Option<Trace>,
+    plugins: &Plugins,
+    timings: &Mutex<Vec<journal::Timing>>,
+) -> (i32, Option<String>) {
+    let groups: Vec<Vec<(String, Command)>> = commands
+        .iter()
+        .map(|command| matrix::expand(&command.0, &command.1))
+        .collect();
+    let labels = labels || groups.iter().any(|runs| runs.len() > 1);
+    let skipped = |group: usize, run: usize| {
+        let skipped: Vec<&str> = groups[group][run..]
+            .iter()
+            .chain(groups[group + 1..].iter().flatten())
+            .map(|run| &run.0[..])
+            .collect();
+        if !skipped.is_empty() {
+            eprintln!("Skipped: {}", skipped.join(", "));
+        }
+    };
+    let execute = |name: &str, run: &(String, Command), invocation: &Invocation| {
+        if let Err(error_msg) = plugins.pre_spawn(&run.0, &run.1.cmd) {
+            eprintln!("error: {}", error_msg);
+            return 1;
+        }
+        let started = SystemTime::now();
+        let result = if run.1.options.background {
+            exec::execute_command(&jobs::prepare(&run.0, &run.1), invocation)
+        } else {
+            exec::execute_command(&run.1, invocation)
+        };
+        let exit_code = exit_code(run, result);
+        if let Some(trace) = trace {
+            trace.record(name, &run.1, &run.0, started, exit_code);
+        }
+        let usage = exec::take_usage();
+        if let Ok(mut timings) = timings.lock() {
+            timings.push(journal::Timing {
+                name: run.0.to_string(),
+                duration: started.elapsed().unwrap_or_default().as_secs_f64(),
+                status: exit_code,
+                user: usage.map(|usage| usage.user.as_secs_f64()),
+                system: usage.map(|usage| usage.system.as_secs_f64()),
+                peak_memory: usage.map(|usage| usage.peak_memory),
+            });
+        }
+        exit_code
+    };
+
+    for (index, runs) in groups.iter().enumerate() {
+        let name = &commands[index].0;
+        let propagate_env = runs[0].1.options.propagate_env;
+        if propagate_env {
+            fs::write(exec::propagated_env_path(), "").ok();
         }
-        let command = &command.1;
-        let exit = execute_command(command, &rest);
 
-        if exit.success() {
-            if index == commands.len() {
-                process::exit(0);
+        let exit_code = if runs.len() > 1 && runs[0].1.options.parallel == Some(true) {
+            if labels {
+                term::status(&format!("\n[{}]", name));
             }
+            // In CI each run's output is printed once they're all done, rather
+            // than interleaved.
+            let buffered = ci::enabled();
+            let exit_codes = if buffered {
+                let runs: Vec<_> = runs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, run)| (run.0.to_string(), ci::buffer(index, &run.1)))
+                    .collect();
+                exec::execute_parallel(&runs, |run| execute(name, run, invocation))
+            } else {
+                exec::execute_parallel(runs, |run| execute(name, run, invocation))
+            };
+            if buffered {
+                for (index, run) in runs.iter().enumerate() {
+                    term::status(&format!("\n[{}]", run.0));
+                    ci::print_output(index);
+                }
+            }
+            let exit_code = exit_codes.into_iter().find(|code| *code != 0).unwrap_or(0);
+            if exit_code == TIMED_OUT {
+                skipped(index, runs.len());
+            }
+            exit_code
         } else {
-            match exit {
-                ExitStatus::Exited(exit_code) => process::exit(exit_code as i32),
-                _ => process::exit(1),
+            let mut exit_code = 0;
+            for (run_index, run) in runs.iter().enumerate() {
+                if labels && !run.1.options.quiet {
+                    term::status(&format!("\n[{}]", run.0));
+                }
+                exit_code = execute(name, run, invocation);
+                if exit_code == TIMED_OUT {
+                    skipped(index, run_index + 1);
+                }
+                if exit_code != 0 {
+                    break;
+                }
+            }
+            exit_code
+        };
+
+        // What the command wrote to CARGO_CMD_ENV is passed on to the ones
+        // after it, including the teardown.
+        if propagate_env {
+            let path = exec::propagated_env_path();
+            match env_file::load(&path) {
+                Ok(vars) => invocation.env.extend(vars),
+                Err(error_msg) => eprintln!("warning: {}", error_msg),
             }
+            fs::remove_file(path).ok();
+        }
+        if exit_code != 0 {
+            return (exit_code, Some(name.to_string()));
         }
     }
+    (0, None)
 }
 
-fn execute_command(command: &str, rest: &Vec<String>) -> ExitStatus {
-    // This is naughty but Exec::shell doesn't let us do it with .args because
-    // it ends up as an argument to sh/cmd.exe instead of our user command
-    // or escaping things weirdly.
-    let command = format!("{} {}", command, rest.join(" "));
-    println!("> {}", command);
-    let sh = Exec::shell(command);
-    sh.join().unwrap_or(ExitStatus::Exited(0))
+// The exit code a command's result comes down to, reporting why it failed
+// where the command itself wouldn't have.
+fn exit_code(command: &(String, Command), result: Result<Option<ExitStatus>, String>) -> i32 {
+    match result {
+        Err(error_msg) => {
+            eprintln!("error: {}", error_msg);
+            COULD_NOT_START
+        }
+        Ok(None) => {
+            eprintln!(
+                "error: \"{}\" was stopped because the maximum run time was exceeded",
+                command.0
+            );
+            TIMED_OUT
+        }
+        Ok(Some(_)) if process_tree::interrupted() => INTERRUPTED,
+        Ok(Some(ExitStatus::Exited(exit_code))) => command.1.options.status(exit_code as i32),
+        Ok(Some(_)) => 1,
+    }
 }
 
 fn unwrap_or_exit<T>(result: Result<T, String>) -> T {
+    unwrap_or_exit_with(result, 1)
+}
+
+fn unwrap_or_exit_with<T>(result: Result<T, String>, exit_code: i32) -> T {
     match result {
         Err(error_msg) => {
-            clap::Error::with_description(&error_msg[..], clap::ErrorKind::InvalidValue).exit();
+            let mut error =
+                clap::Error::with_description(&error_msg[..], clap::ErrorKind::InvalidValue);
+            if let Some(color) = term::color() {
+                let label = if color {
+                    "\x1b[1;31merror:\x1b[0m"
+                } else {
+                    "error:"
+                };
+                error.message = format!("{} {}", label, error_msg);
+            }
+            eprintln!("{}", error.message);
+            process::exit(exit_code);
         }
         Ok(thing) => thing,
     }
 }
 
-fn get_commands(command: &str) -> Result<Vec<(String, String)>, String> {
-    let mut cargo_toml = File::open("Cargo.toml").or(Err(
-        "Could not find or open Cargo.toml in the current directory",
-    ))?;
-    let mut cargo_str = String::new();
+fn get_commands(
+    cargo_commands: &mut HashMap<String, Command>,
+    command: &str,
+) -> Result<Vec<(String, Command)>, String> {
+    if command::steps(cargo_commands, command).is_empty() {
+        return Err(suggest::not_found(cargo_commands, command));
+    }
     let mut commands = vec![];
-    let names = vec![
+    let mut names = vec![
         format!("pre{}", command),
         command.to_string(),
         format!("post{}", command),
     ];
+    // Wildcard hooks wrap the command specific ones.
+    if !WILDCARD_HOOKS.contains(&command) {
+        names.insert(0, WILDCARD_HOOKS[0].to_string());
+        names.push(WILDCARD_HOOKS[1].to_string());
+    }
 
-    cargo_toml
-        .read_to_string(&mut cargo_str)
-        .or(Err("Could not read the contents of Cargo.toml"))?;
-
-    let cargo_toml: Cargotoml =
-        toml::from_str(&cargo_str[..]).or(Err("Could not find commands in Cargo.toml"))?;
-
-    let cargo_commands = cargo_toml.package.metadata.commands;
-
-    for name in names {
-        let command_to_run = &cargo_commands.get(&name);
-
-        if name == command && command_to_run.is_none() {
-            return Err(format!("Command \"{}\" not found in Cargo.toml", &command));
-        }
-
-        if command_to_run.is_some() {
-            commands.push((name, command_to_run.unwrap().to_string()));
+    // Hooks and commands can be lists of steps, which run in order.
+    let steps: Vec<String> = names
+        .iter()
+        .flat_map(|name| command::steps(cargo_commands, name))
+        .cloned()
+        .collect();
+    for name in steps {
+        let command_to_run = cargo_commands.remove(&name);
+        if let Some(command_to_run) = command_to_run {
+            commands.push((name, command_to_run));
         }
     }
 
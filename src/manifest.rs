@@ -0,0 +1,563 @@
+use cache::{self, Source};
+use cargo_make;
+use command::{self, Command};
+use glob::glob;
+use notify::Notifications;
+use package_json;
+use std::collections::{BTreeMap, HashMap};
+use std::env::consts;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::path::{Path, PathBuf};
+use toml_edit::{ImDocument, TableLike};
+
+// `[package.metadata.cargo-cmd]`, which configures cargo-cmd itself. It's a
+// table of its own so that none of its keys can take the name of a command.
+#[derive(Deserialize, Debug, Default)]
+pub struct Settings {
+    pub notifications: Option<Notifications>,
+    // Run the cargo subcommand of the same name for commands that aren't
+    // defined.
+    #[serde(default)]
+    pub fallback_to_cargo: bool,
+    #[serde(default)]
+    pub plugins: Vec<String>,
+    // Groups of commands that run together, keyed by the name they're run as.
+    #[serde(default)]
+    pub services: HashMap<String, BTreeMap<String, Command>>,
+    // The rest is only used to read the commands.
+    #[serde(default)]
+    include: Include,
+    #[serde(default)]
+    strict_metadata: bool,
+    // Defaults for the environment options of every command.
+    env_clear: Option<bool>,
+    env_allow: Option<Vec<String>>,
+    env_deny: Option<Vec<String>>,
+    // Added after the directories of each command's own `path_prepend`.
+    #[serde(default)]
+    path_prepend: Vec<String>,
+    // Whether the runs of a matrix happen at the same time, unless the
+    // command says otherwise.
+    parallel: Option<bool>,
+    // Manifests of other parts of the repository to take commands from.
+    #[serde(default)]
+    extra_manifests: Vec<String>,
+    // Only checked in strict mode.
+    #[serde(flatten)]
+    unknown: HashMap<String, toml::Value>,
+}
+
+impl Settings {
+    // Fill in the options of `command` that it leaves to the settings.
+    fn apply_defaults(&self, command: &mut Command) {
+        let options = &mut command.options;
+        options.env_clear = options.env_clear.or(self.env_clear);
+        options.parallel = options.parallel.or(self.parallel);
+        options.env_allow = options.env_allow.take().or(self.env_allow.clone());
+        options.env_deny = options.env_deny.take().or(self.env_deny.clone());
+        options
+            .path_prepend
+            .extend(self.path_prepend.iter().cloned());
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Include {
+    Paths(Paths),
+    // Keyed by `all`, the OS (`linux`, `macos`, `windows`, ...) or the OS
+    // family (`unix`, `windows`).
+    ByOs(HashMap<String, Paths>),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Paths {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Include {
+    fn default() -> Include {
+        Include::Paths(Paths::Many(vec![]))
+    }
+}
+
+impl Paths {
+    fn to_vec(&self) -> Vec<String> {
+        match self {
+            Paths::One(path) => vec![path.to_string()],
+            Paths::Many(paths) => paths.to_vec(),
+        }
+    }
+}
+
+impl Include {
+    // Only the paths that apply to the platform we're running on.
+    fn patterns(&self) -> Vec<String> {
+        match self {
+            Include::Paths(paths) => paths.to_vec(),
+            Include::ByOs(by_os) => ["all", consts::FAMILY, consts::OS]
+                .iter()
+                .filter_map(|key| by_os.get(*key))
+                .flat_map(Paths::to_vec)
+                .collect(),
+        }
+    }
+}
+
+// Where a command is defined: the file, the key of its table in the file and
+// the line that key is on.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub path: PathBuf,
+    pub key: String,
+    pub line: Option<usize>,
+}
+
+impl Origin {
+    // Find `key` in the file at `path`, shown relative to `dir`.
+    fn locate(dir: &Path, path: &Path, key: &[&str]) -> Origin {
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension == "json");
+        let line = read(path).ok().and_then(|text| {
+            if is_json {
+                json_line(&text, key)
+            } else {
+                toml_line(&text, key)
+            }
+        });
+        Origin {
+            path: path.strip_prefix(dir).unwrap_or(path).to_path_buf(),
+            key: key.join("."),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.path.display(), line),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+fn toml_line(text: &str, key: &[&str]) -> Option<usize> {
+    let document = ImDocument::parse(text).ok()?;
+    let (last, tables) = key.split_last()?;
+    let mut table: &dyn TableLike = document.as_table();
+    for name in tables {
+        table = table.get(name)?.as_table_like()?;
+    }
+    let (key, _) = table.get_key_value(last)?;
+    Some(line_of(text, key.span()?.start))
+}
+
+// JSON isn't parsed with positions, so this is the first line with the last
+// part of the key after the line with the first part.
+fn json_line(text: &str, key: &[&str]) -> Option<usize> {
+    let mut offset = 0;
+    for name in key {
+        offset += text[offset..].find(&format!("\"{}\"", name))?;
+    }
+    Some(line_of(text, offset))
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).or(Err(format!("Could not find or open {}", path.display())))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).or(Err(format!(
+        "Could not read the contents of {}",
+        path.display()
+    )))?;
+    Ok(contents)
+}
+
+// A file of commands, which can be lists of steps.
+fn parse_steps(contents: &str, path: &Path) -> Result<HashMap<String, Command>, String> {
+    command::deserialize_steps(&mut toml::Deserializer::new(contents))
+        .map_err(|error| format!("Could not parse commands in {}: {}", path.display(), error))
+}
+
+// The `[package]` table of the manifest in `dir`.
+pub fn package(dir: &Path) -> Option<toml::Value> {
+    let mut cargo_toml: toml::Value = toml::from_str(&read(&dir.join("Cargo.toml")).ok()?).ok()?;
+    cargo_toml.as_table_mut()?.remove("package")
+}
+
+pub fn package_name(dir: &Path) -> Option<String> {
+    package(dir)?.get("name")?.as_str().map(str::to_string)
+}
+
+// The values in the package's `[package.metadata]`, other than the commands
+// and the settings of cargo-cmd, as `metadata.<key>.<key>` for `${...}`
+// placeholders. Arrays are joined with spaces.
+pub fn metadata_values(dir: &Path) -> HashMap<String, String> {
+    fn flatten(prefix: String, value: &toml::Value, values: &mut HashMap<String, String>) {
+        match value {
+            toml::Value::Table(table) => {
+                for (key, value) in table {
+                    flatten(format!("{}.{}", prefix, key), value, values);
+                }
+            }
+            toml::Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(scalar).collect();
+                values.insert(prefix, items.join(" "));
+            }
+            value => {
+                values.insert(prefix, scalar(value));
+            }
+        }
+    }
+    fn scalar(value: &toml::Value) -> String {
+        match value {
+            toml::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    let mut values = HashMap::new();
+    if let Some(toml::Value::Table(metadata)) =
+        package(dir).and_then(|mut package| package.as_table_mut()?.remove("metadata"))
+    {
+        for (key, value) in metadata
+            .iter()
+            .filter(|(key, _)| *key != "commands" && *key != "cargo-cmd")
+        {
+            flatten(format!("metadata.{}", key), value, &mut values);
+        }
+    }
+    values
+}
+
+// The `[package.metadata]` table of the package.
+fn metadata_table(package: Option<toml::Value>) -> toml::value::Table {
+    match package.and_then(|mut package| package.as_table_mut()?.remove("metadata")) {
+        Some(toml::Value::Table(metadata)) => metadata,
+        _ => toml::value::Table::new(),
+    }
+}
+
+fn parse_settings(metadata: &mut toml::value::Table) -> Result<Settings, String> {
+    let mut settings: Settings = match metadata.remove("cargo-cmd") {
+        Some(settings) => settings.try_into().map_err(|error| {
+            format!(
+                "Invalid [package.metadata.cargo-cmd] in Cargo.toml: {}",
+                error
+            )
+        })?,
+        None => Settings::default(),
+    };
+    // Services get the same defaults as the commands.
+    let mut services = mem::take(&mut settings.services);
+    for command in services.values_mut().flat_map(BTreeMap::values_mut) {
+        settings.apply_defaults(command);
+    }
+    settings.services = services;
+    Ok(settings)
+}
+
+pub fn settings(dir: &Path) -> Result<Settings, String> {
+    parse_settings(&mut metadata_table(package(dir)))
+}
+
+// Collect every command available from the manifest in `dir`, from the cache
+// when none of the files they came from have changed since the last run.
+pub fn load_commands(
+    dir: &Path,
+    strict: bool,
+    use_cache: bool,
+) -> Result<HashMap<String, Command>, String> {
+    if use_cache {
+        if let Some(commands) = cache::load(dir, strict) {
+            return Ok(commands);
+        }
+    }
+    let mut sources = Some(vec![]);
+    let commands = parse_commands(dir, strict, &mut sources, &mut None)?;
+    if let (true, Some(sources)) = (use_cache, sources) {
+        cache::store(dir, strict, sources, &commands);
+    }
+    Ok(commands)
+}
+
+// Where each command is defined, in the order the definitions override each
+// other. Read without the cache, which doesn't keep track of them.
+pub fn load_origins(dir: &Path, strict: bool) -> Result<HashMap<String, Vec<Origin>>, String> {
+    let mut origins = Some(HashMap::new());
+    parse_commands(dir, strict, &mut None, &mut origins)?;
+    Ok(origins.unwrap_or_default())
+}
+
+// Commands in Cargo.toml override included ones, which in turn override tasks
+// imported from cargo-make. Two included files defining the same command is a
+// warning, where the file included last wins. In strict mode that is an
+// error, as are keys that cargo-cmd doesn't know. Every file read is added to
+// `sources`, which is cleared when the result can't be cached, and where each
+// command is defined to `origins`, if they're wanted.
+fn parse_commands(
+    dir: &Path,
+    strict: bool,
+    sources: &mut Option<Vec<Source>>,
+    origins: &mut Option<HashMap<String, Vec<Origin>>>,
+) -> Result<HashMap<String, Command>, String> {
+    let mut add_origin = |name: &str, path: &Path, key: &[&str]| {
+        if let Some(origins) = origins {
+            origins
+                .entry(name.to_string())
+                .or_default()
+                .push(Origin::locate(dir, path, key));
+        }
+    };
+    add_source(sources, &dir.join("Cargo.toml"));
+    let cargo_str = read(&dir.join("Cargo.toml")).or(Err(
+        "Could not find or open Cargo.toml in the current directory",
+    ))?;
+    let mut cargo_toml: toml::Value =
+        toml::from_str(&cargo_str[..]).or(Err("Could not find commands in Cargo.toml"))?;
+    let package = cargo_toml
+        .as_table_mut()
+        .and_then(|cargo_toml| cargo_toml.remove("package"))
+        .ok_or("Could not find commands in Cargo.toml")?;
+    let mut metadata = metadata_table(Some(package));
+    let settings = parse_settings(&mut metadata)?;
+    let manifest_commands = match metadata.remove("commands") {
+        Some(commands) => command::deserialize_steps(commands).map_err(|error| {
+            format!(
+                "Invalid [package.metadata.commands] in Cargo.toml: {}",
+                error
+            )
+        })?,
+        None => HashMap::new(),
+    };
+    let strict = strict || settings.strict_metadata;
+
+    add_source(sources, &dir.join("Makefile.toml"));
+    let mut commands: HashMap<String, Command> = cargo_make::load(&dir.join("Makefile.toml"))
+        .into_iter()
+        .map(|(name, cmd)| (name, Command::from(cmd)))
+        .collect();
+    for name in commands.keys() {
+        add_origin(name, &dir.join("Makefile.toml"), &["tasks", name]);
+    }
+    let mut included: HashMap<String, PathBuf> = HashMap::new();
+
+    for path in include_paths(dir, &settings.include.patterns(), sources)? {
+        add_source(sources, &path);
+        let file_commands = parse_steps(&read(&path)?, &path)?;
+        if strict {
+            check_unknown_keys(dir, &file_commands, &path, &[])?;
+        }
+        for (name, command) in file_commands {
+            // The steps of a list are all defined at the list.
+            let key = command::base_name(&name, &command);
+            if let Some(other) = included.get(&name) {
+                let error = format!(
+                    "Command \"{}\" is defined in both {} and {}",
+                    name,
+                    Origin::locate(dir, other, &[key]),
+                    Origin::locate(dir, &path, &[key])
+                );
+                if strict {
+                    return Err(error);
+                }
+                // Cached runs would no longer show the warning.
+                *sources = None;
+                eprintln!("warning: {}, using {}", error, path.display());
+            }
+            add_origin(&name, &path, &[key]);
+            included.insert(name.to_string(), path.to_path_buf());
+            commands.insert(name, command);
+        }
+    }
+
+    for manifest in &settings.extra_manifests {
+        let path = dir.join(manifest);
+        add_source(sources, &path);
+        // The lock files decide which package manager runs the scripts.
+        if let (true, Some(parent)) = (manifest.ends_with("package.json"), path.parent()) {
+            for lock_file in &package_json::LOCK_FILES {
+                add_source(sources, &parent.join(lock_file));
+            }
+        }
+        for (name, command) in extra_commands(dir, manifest, strict)? {
+            // The name in the manifest itself, without the namespace.
+            let key = name.split_once(':').map_or(&name[..], |(_, key)| key);
+            let key = command::base_name(key, &command);
+            if manifest.ends_with("package.json") {
+                add_origin(&name, &path, &["scripts", key]);
+            } else {
+                add_origin(&name, &path, &[key]);
+            }
+            commands.insert(name, command);
+        }
+    }
+
+    if strict {
+        let cargo_toml = dir.join("Cargo.toml");
+        let table = ["package", "metadata", "commands"];
+        check_unknown_keys(dir, &manifest_commands, &cargo_toml, &table)?;
+        let table = ["package", "metadata", "cargo-cmd"];
+        let mut keys: Vec<&String> = settings.unknown.keys().collect();
+        keys.sort();
+        if let Some(key) = keys.first() {
+            let mut key_path = table.to_vec();
+            key_path.push(key);
+            return Err(format!(
+                "Unknown key \"{}\" in the settings in {}",
+                key,
+                Origin::locate(dir, &cargo_toml, &key_path)
+            ));
+        }
+        let mut groups: Vec<&String> = settings.services.keys().collect();
+        groups.sort();
+        for group in groups {
+            let table = ["package", "metadata", "cargo-cmd", "services", group];
+            check_unknown_keys(dir, &settings.services[group], &cargo_toml, &table)?;
+        }
+    }
+    for (name, command) in &manifest_commands {
+        let key = [
+            "package",
+            "metadata",
+            "commands",
+            command::base_name(name, command),
+        ];
+        add_origin(name, &dir.join("Cargo.toml"), &key);
+    }
+    commands.extend(manifest_commands);
+    for command in commands.values_mut() {
+        settings.apply_defaults(command);
+    }
+
+    if commands.is_empty() {
+        return Err("Could not find commands in Cargo.toml".to_string());
+    }
+
+    Ok(commands)
+}
+
+// The commands of a manifest from `extra_manifests`, named `<dir>:<name>`
+// after the directory the manifest is in, which is also where they run. A
+// `package.json` has its scripts run, any other manifest holds commands like
+// the included files do.
+fn extra_commands(
+    dir: &Path,
+    manifest: &str,
+    strict: bool,
+) -> Result<HashMap<String, Command>, String> {
+    let path = dir.join(manifest);
+    let manifest_dir = Path::new(manifest)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let namespace = if manifest_dir.as_os_str().is_empty() {
+        Path::new(manifest)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+    } else {
+        manifest_dir.to_string_lossy()
+    }
+    .replace('\\', "/");
+    let is_package_json = path.file_name().is_some_and(|name| name == "package.json");
+    let commands: HashMap<String, Command> = if is_package_json {
+        package_json::load(&path)?
+            .into_iter()
+            .map(|(name, cmd)| (name, Command::from(cmd)))
+            .collect()
+    } else {
+        let commands = parse_steps(&read(&path)?, &path)?;
+        if strict {
+            check_unknown_keys(dir, &commands, &path, &[])?;
+        }
+        commands
+    };
+
+    Ok(commands
+        .into_iter()
+        .map(|(name, mut command)| {
+            if !manifest_dir.as_os_str().is_empty() {
+                let cwd = match command.options.cwd {
+                    Some(ref cwd) => manifest_dir.join(cwd),
+                    None => manifest_dir.to_path_buf(),
+                };
+                command.options.cwd = Some(cwd.to_string_lossy().into_owned());
+            }
+            (format!("{}:{}", namespace, name), command)
+        })
+        .collect())
+}
+
+fn add_source(sources: &mut Option<Vec<Source>>, path: &Path) {
+    if let Some(sources) = sources {
+        sources.push(Source::new(path));
+    }
+}
+
+// Commands with keys cargo-cmd doesn't know, where `table` is the key of the
+// table of commands in the file at `path`.
+fn check_unknown_keys<'a>(
+    dir: &Path,
+    commands: impl IntoIterator<Item = (&'a String, &'a Command)>,
+    path: &Path,
+    table: &[&str],
+) -> Result<(), String> {
+    let mut commands: Vec<(&String, &Command)> = commands.into_iter().collect();
+    commands.sort_by_key(|(name, _)| *name);
+    for (name, command) in commands {
+        if let Some(key) = command.unknown_keys().first() {
+            let mut command_key = table.to_vec();
+            command_key.push(command::base_name(name, command));
+            return Err(format!(
+                "Unknown key \"{}\" in command \"{}\" in {}",
+                key,
+                name,
+                Origin::locate(dir, path, &command_key)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn include_paths(
+    dir: &Path,
+    patterns: &[String],
+    sources: &mut Option<Vec<Source>>,
+) -> Result<Vec<PathBuf>, String> {
+    let is_glob = |pattern: &Path| pattern.to_string_lossy().contains(|c| "*?[".contains(c));
+    let mut paths = vec![];
+    for pattern in patterns {
+        let full_pattern = dir.join(pattern);
+        // Files added to or removed from the directory a glob looks in change
+        // its modification time, but that doesn't work for nested globs.
+        if is_glob(&full_pattern) {
+            match full_pattern.parent() {
+                Some(parent) if !is_glob(parent) => add_source(sources, parent),
+                _ => *sources = None,
+            }
+        }
+        let mut matches: Vec<PathBuf> = glob(&full_pattern.to_string_lossy())
+            .or(Err(format!("Invalid include pattern \"{}\"", pattern)))?
+            .filter_map(Result::ok)
+            .collect();
+        // A plain path that doesn't exist is most likely a typo, but a glob
+        // is allowed to match nothing.
+        if matches.is_empty() && !is_glob(Path::new(pattern)) {
+            return Err(format!("Could not find or open {}", full_pattern.display()));
+        }
+        matches.sort();
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
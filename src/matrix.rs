@@ -0,0 +1,56 @@
+use command::Command;
+use interpolate;
+use std::collections::HashMap;
+
+// Expand a command with a `matrix` into one run for each combination of its
+// values, labelled with them. The values are available as `${name}`
+// placeholders and `CARGO_CMD_MATRIX_<NAME>` variables.
+pub fn expand(name: &str, command: &Command) -> Vec<(String, Command)> {
+    if command.options.matrix.is_empty() {
+        return vec![(name.to_string(), command.clone())];
+    }
+    let mut combinations: Vec<Vec<(&String, &String)>> = vec![vec![]];
+    for (key, values) in &command.options.matrix {
+        combinations = combinations
+            .iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key, value));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    combinations
+        .into_iter()
+        .map(|combination| {
+            let label: Vec<String> = combination
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            let values: HashMap<String, String> = combination
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let mut run = command.clone();
+            if command.options.interpolate != Some(false) {
+                run.cmd = interpolate::interpolate(&command.cmd, &values);
+            }
+            run.options.matrix_env = combination
+                .iter()
+                .map(|(key, value)| (env_name(key), value.to_string()))
+                .collect();
+            (format!("{} {}", name, label.join(" ")), run)
+        })
+        .collect()
+}
+
+fn env_name(name: &str) -> String {
+    format!(
+        "CARGO_CMD_MATRIX_{}",
+        name.to_uppercase()
+            .replace(|c: char| !c.is_alphanumeric(), "_")
+    )
+}
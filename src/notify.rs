@@ -0,0 +1,74 @@
+// Webhook notifications for when commands finish. The payload has `text` for
+// Slack and `content` for Discord next to the details for other receivers.
+
+use duration;
+use serde_json::json;
+use std::time::Duration;
+use ureq;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug)]
+pub struct Notifications {
+    webhook: String,
+    // Every command when empty.
+    #[serde(default)]
+    commands: Vec<String>,
+    #[serde(default)]
+    on: On,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum On {
+    #[default]
+    Always,
+    Success,
+    Failure,
+}
+
+impl Notifications {
+    pub fn send(
+        &self,
+        package: &str,
+        command: &str,
+        duration: Duration,
+        exit_code: i32,
+    ) -> Result<(), String> {
+        let success = exit_code == 0;
+        if !self.commands.is_empty() && !self.commands.iter().any(|c| c == command)
+            || self.on == On::Success && !success
+            || self.on == On::Failure && success
+        {
+            return Ok(());
+        }
+        let outcome = if success {
+            "succeeded".to_string()
+        } else {
+            format!("failed with exit code {}", exit_code)
+        };
+        let text = format!(
+            "`cargo cmd {}` {} in {} after {}",
+            command,
+            outcome,
+            package,
+            duration::format(duration)
+        );
+        let payload = json!({
+            "text": text,
+            "content": text,
+            "package": package,
+            "command": command,
+            "duration": duration.as_secs_f64(),
+            "status": exit_code,
+        });
+        ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .build()
+            .post(&self.webhook)
+            .set("Content-Type", "application/json")
+            .send_string(&payload.to_string())
+            .map(|_| ())
+            .map_err(|e| format!("Could not send the notification: {}", e))
+    }
+}
@@ -0,0 +1,45 @@
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// The lock files of the package managers other than npm.
+pub const LOCK_FILES: [&str; 2] = ["pnpm-lock.yaml", "yarn.lock"];
+
+#[derive(Deserialize, Debug)]
+struct PackageJson {
+    #[serde(default)]
+    scripts: HashMap<String, String>,
+}
+
+// The package manager the project uses, going by its lock file.
+fn package_manager(dir: &Path) -> &'static str {
+    if dir.join(LOCK_FILES[0]).is_file() {
+        "pnpm"
+    } else if dir.join(LOCK_FILES[1]).is_file() {
+        "yarn"
+    } else {
+        "npm"
+    }
+}
+
+// Map the scripts of a `package.json` onto commands that run them with the
+// package manager, which adds `node_modules/.bin` to the PATH and runs the
+// script's own pre and post scripts.
+pub fn load(path: &Path) -> Result<HashMap<String, String>, String> {
+    let contents =
+        fs::read_to_string(path).or(Err(format!("Could not find or open {}", path.display())))?;
+    let package: PackageJson = serde_json::from_str(&contents).or(Err(format!(
+        "Could not parse scripts in {}",
+        path.display()
+    )))?;
+    let package_manager = package_manager(path.parent().unwrap_or_else(|| Path::new(".")));
+    Ok(package
+        .scripts
+        .into_keys()
+        .map(|name| {
+            let cmd = format!("{} run {}", package_manager, name);
+            (name, cmd)
+        })
+        .collect())
+}
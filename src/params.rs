@@ -0,0 +1,203 @@
+use ci;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use toml;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "RawParam", into = "RawParam")]
+pub struct Param {
+    pub name: String,
+    pub default: Option<String>,
+    pub required: bool,
+    pub choices: Vec<String>,
+    pub unknown: HashMap<String, toml::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum RawParam {
+    Name(String),
+    Table {
+        name: String,
+        default: Option<String>,
+        required: Option<bool>,
+        #[serde(default)]
+        choices: Vec<String>,
+        #[serde(flatten)]
+        unknown: HashMap<String, toml::Value>,
+    },
+}
+
+impl From<RawParam> for Param {
+    fn from(raw: RawParam) -> Param {
+        match raw {
+            RawParam::Name(name) => Param {
+                name,
+                default: None,
+                required: true,
+                choices: vec![],
+                unknown: HashMap::new(),
+            },
+            RawParam::Table {
+                name,
+                default,
+                required,
+                choices,
+                unknown,
+            } => Param {
+                required: required.unwrap_or(default.is_none()),
+                name,
+                default,
+                choices,
+                unknown,
+            },
+        }
+    }
+}
+
+impl From<Param> for RawParam {
+    fn from(param: Param) -> RawParam {
+        RawParam::Table {
+            name: param.name,
+            default: param.default,
+            required: Some(param.required),
+            choices: param.choices,
+            unknown: param.unknown,
+        }
+    }
+}
+
+// Pull `name=value` arguments for the declared params out of the extra
+// arguments. Everything else is left to be passed through to the command.
+pub fn resolve(
+    command: &str,
+    params: &[Param],
+    rest: &[String],
+) -> Result<(HashMap<String, String>, Vec<String>), String> {
+    let mut values = HashMap::new();
+    let mut remaining = vec![];
+
+    for arg in rest {
+        let declared = arg.find('=').and_then(|index| {
+            params
+                .iter()
+                .find(|param| param.name == arg[..index])
+                .map(|param| (param, &arg[index + 1..]))
+        });
+        match declared {
+            Some((param, value)) => {
+                values.insert(param.name.to_string(), value.to_string());
+            }
+            None => remaining.push(arg.to_string()),
+        }
+    }
+
+    let interactive = ci::interactive();
+    let mut missing = vec![];
+    for param in params {
+        if let Some(value) = values.get(&param.name) {
+            check_choice(param, value)?;
+            continue;
+        }
+        // Required params are asked for when someone is there to answer,
+        // with the default (if any) offered as the answer.
+        let value = if param.required && interactive {
+            prompt(param)?
+        } else {
+            match param.default {
+                Some(ref default) => default.to_string(),
+                None if param.required => {
+                    missing.push(&param.name[..]);
+                    continue;
+                }
+                None => String::new(),
+            }
+        };
+        values.insert(param.name.to_string(), value);
+    }
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "Missing required parameter{} {} for command \"{}\"\n\nUSAGE:\n    cargo cmd {} {}",
+            if missing.len() > 1 { "s" } else { "" },
+            missing
+                .iter()
+                .map(|name| format!("\"{}\"", name))
+                .collect::<Vec<_>>()
+                .join(", "),
+            command,
+            command,
+            usage(params)
+        ));
+    }
+
+    Ok((values, remaining))
+}
+
+fn check_choice(param: &Param, value: &str) -> Result<(), String> {
+    if param.choices.is_empty() || param.choices.iter().any(|choice| choice == value) {
+        return Ok(());
+    }
+    Err(format!(
+        "Invalid value \"{}\" for parameter \"{}\", expected one of: {}",
+        value,
+        param.name,
+        param.choices.join(", ")
+    ))
+}
+
+fn prompt(param: &Param) -> Result<String, String> {
+    let stdin = io::stdin();
+    let mut question = param.name.to_string();
+    if !param.choices.is_empty() {
+        question.push_str(&format!(" ({})", param.choices.join(", ")));
+    }
+    if let Some(ref default) = param.default {
+        question.push_str(&format!(" [{}]", default));
+    }
+
+    loop {
+        eprint!("{}: ", question);
+        io::stderr().flush().ok();
+
+        let mut answer = String::new();
+        let read = stdin
+            .lock()
+            .read_line(&mut answer)
+            .or(Err("Could not read from stdin"))?;
+        if read == 0 {
+            return Err(format!("Missing required parameter \"{}\"", param.name));
+        }
+
+        let answer = match (answer.trim(), &param.default) {
+            ("", Some(default)) => default.to_string(),
+            ("", None) => continue,
+            (answer, _) => answer.to_string(),
+        };
+        match check_choice(param, &answer) {
+            Ok(_) => return Ok(answer),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
+
+fn usage(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| match param.default {
+            Some(ref default) => format!("[{}={}]", param.name, default),
+            None if param.required => format!("{}=<{}>", param.name, param.name),
+            None => format!("[{}=<{}>]", param.name, param.name),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Environment variable a param's value is exposed to the command as.
+pub fn env_name(name: &str) -> String {
+    format!(
+        "CARGO_CMD_PARAM_{}",
+        name.to_uppercase()
+            .replace(|c: char| !c.is_alphanumeric(), "_")
+    )
+}
@@ -0,0 +1,88 @@
+// Plugins are programs named `cargo-cmd-<plugin>` on the PATH, for the names
+// listed in `plugins`. At each point of a run they're called with the event as
+// their argument and its details as JSON on stdin. A plugin failing before a
+// command starts stops it, so plugins can enforce policies.
+
+use serde_json::{json, Value};
+use std::io;
+use std::time::Duration;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+pub struct Plugins {
+    names: Vec<String>,
+    package: String,
+}
+
+impl Plugins {
+    pub fn new(names: &[String], package: &str) -> Plugins {
+        Plugins {
+            names: names.to_vec(),
+            package: package.to_string(),
+        }
+    }
+
+    fn call(&self, event: &str, mut details: Value) -> Result<(), String> {
+        details["event"] = json!(event);
+        details["package"] = json!(self.package);
+        let input = details.to_string();
+        for name in &self.names {
+            let program = format!("cargo-cmd-{}", name);
+            let mut process = Exec::cmd(&program)
+                .arg(event)
+                .stdin(Redirection::Pipe)
+                .popen()
+                .or(Err(format!("Could not find or run plugin {}", program)))?;
+            // A plugin doesn't have to read the details, and can exit before
+            // they're all written.
+            match process.communicate(Some(&input)) {
+                Err(ref error) if error.kind() != io::ErrorKind::BrokenPipe => {
+                    return Err(format!(
+                        "Could not pass the details of {} to plugin {}: {}",
+                        event, program, error
+                    ))
+                }
+                _ => {}
+            }
+            match process.wait() {
+                Ok(ExitStatus::Exited(0)) => {}
+                Ok(_) => return Err(format!("Plugin \"{}\" failed on {}", name, event)),
+                Err(error) => {
+                    return Err(format!("Could not wait for plugin {}: {}", program, error))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Before the commands to run for `command` are worked out.
+    pub fn pre_resolution(&self, command: &str, args: &[String]) -> Result<(), String> {
+        self.call(
+            "pre-resolution",
+            json!({ "command": command, "args": args }),
+        )
+    }
+
+    // Before each command is started.
+    pub fn pre_spawn(&self, name: &str, cmd: &str) -> Result<(), String> {
+        self.call("pre-spawn", json!({ "command": name, "cmd": cmd }))
+    }
+
+    // Once everything has run.
+    pub fn post_run(
+        &self,
+        command: &str,
+        args: &[String],
+        duration: Duration,
+        status: i32,
+    ) -> Result<(), String> {
+        self.call(
+            "post-run",
+            json!({
+                "command": command,
+                "args": args,
+                "duration": duration.as_secs_f64(),
+                "status": status,
+            }),
+        )
+    }
+}
@@ -0,0 +1,519 @@
+// A spawned command along with everything it starts in turn. Shell commands
+// regularly leave grandchildren behind when only the shell itself is killed,
+// so on Unix each command gets its own process group and on Windows its own
+// job object, which are then terminated as a whole.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use subprocess::{ExitStatus, Popen};
+
+// The trees of the commands that are currently running, for the Ctrl-C
+// handler. Holds process group ids on Unix and job handles on Windows.
+static CURRENT: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Limits that apply to each process of the tree on its own.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Limits {
+    pub memory: Option<u64>,
+    pub cpu_time: Option<Duration>,
+}
+
+// What the processes of a tree used, see `ProcessTree::usage`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    pub user: Duration,
+    pub system: Duration,
+    pub peak_memory: u64,
+}
+
+pub struct ProcessTree {
+    id: usize,
+    #[cfg(unix)]
+    foreground: bool,
+    // Known once the process has been waited for on Unix.
+    #[cfg(unix)]
+    usage: Option<Usage>,
+}
+
+// Forward Ctrl-C and termination signals to the running command's tree, as it
+// might not be in our process group to receive them itself.
+pub fn install_interrupt_handler() {
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        for id in current().iter().filter(|id| **id != 0) {
+            os::interrupt(*id);
+        }
+    })
+    .ok();
+}
+
+// The command line that runs `argv` with the given limits. On Unix they're
+// rlimits, which the shell set in front of it sets for itself with `ulimit`
+// before it replaces itself with the command, so they only ever apply to the
+// command's own processes and never to cargo-cmd or the other commands it is
+// running at the same time. On Windows the job is limited once the process is
+// attached to it, see `ProcessTree::set_limits`.
+pub fn with_limits(limits: &Limits, argv: Vec<String>) -> Vec<String> {
+    os::with_limits(limits, argv)
+}
+
+fn current() -> MutexGuard<'static, Vec<usize>> {
+    CURRENT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Lets the next run start after an interrupted one.
+pub fn reset_interrupted() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_running(pid: u32) -> bool {
+    os::is_running(pid)
+}
+
+// Stop a process that was left running in the background by an earlier run.
+pub fn stop(pid: u32) {
+    os::stop(pid);
+}
+
+// Kill a process that didn't exit after `stop`.
+pub fn force_stop(pid: u32) {
+    os::force_stop(pid);
+}
+
+impl ProcessTree {
+    pub fn new(process: &Popen) -> ProcessTree {
+        let pid = process.pid().unwrap_or(0);
+        let mut current = current();
+        // When commands run in parallel only the first one gets the terminal.
+        let tree = os::attach(pid, current.is_empty());
+        current.push(tree.id);
+        tree
+    }
+
+    pub fn set_niceness(&self, niceness: i32) -> Result<(), String> {
+        os::set_niceness(self.id, niceness).or(Err(format!(
+            "Could not change the priority to {}",
+            niceness
+        )))
+    }
+
+    pub fn set_limits(&self, limits: &Limits) -> Result<(), String> {
+        os::set_limits(self.id, limits).or(Err("Could not apply the resource limits".to_string()))
+    }
+
+    // Wait for the process the tree was created for, up to `timeout`.
+    pub fn wait(
+        &mut self,
+        process: &mut Popen,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ExitStatus>, ()> {
+        os::wait(self, process, timeout)
+    }
+
+    // The resources used by the tree's processes, once they have exited.
+    pub fn usage(&self) -> Option<Usage> {
+        os::usage(self)
+    }
+
+    pub fn terminate(&self) {
+        os::terminate(self.id);
+    }
+
+    pub fn kill(&self) {
+        os::kill(self.id);
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        current().retain(|id| *id != self.id);
+        os::release(self);
+    }
+}
+
+#[cfg(unix)]
+mod os {
+    use super::{Limits, ProcessTree, Usage};
+    use libc;
+    use std::time::{Duration, Instant};
+    use std::{io, mem, thread};
+    use subprocess::{ExitStatus, Popen};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn attach(pid: u32, foreground: bool) -> ProcessTree {
+        let pgid = pid as libc::pid_t;
+        // When attached to a terminal the command's group has to become the
+        // foreground group, otherwise it can't read from the terminal and
+        // wouldn't get Ctrl-C. That is what shells do for each job too.
+        let foreground = unsafe {
+            libc::setpgid(pgid, pgid);
+            if foreground && libc::isatty(0) == 1 && libc::tcgetpgrp(0) == libc::getpgrp() {
+                libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+                libc::tcsetpgrp(0, pgid);
+                libc::killpg(pgid, libc::SIGCONT);
+                true
+            } else {
+                false
+            }
+        };
+        ProcessTree {
+            id: pid as usize,
+            foreground,
+            usage: None,
+        }
+    }
+
+    fn to_duration(time: libc::timeval) -> Duration {
+        Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+    }
+
+    // The process is reaped here rather than by `Popen`, as only wait4 tells
+    // what it and the children it waited for used.
+    pub fn wait(
+        tree: &mut ProcessTree,
+        _: &mut Popen,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ExitStatus>, ()> {
+        let give_up = timeout.map(|timeout| Instant::now() + timeout);
+        let flags = if give_up.is_some() { libc::WNOHANG } else { 0 };
+        loop {
+            let mut status = 0;
+            let mut usage: libc::rusage = unsafe { mem::zeroed() };
+            match unsafe { libc::wait4(tree.id as libc::pid_t, &mut status, flags, &mut usage) } {
+                -1 => {
+                    if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                        return Err(());
+                    }
+                }
+                0 => {
+                    let remaining = give_up.map_or(Duration::ZERO, |give_up| {
+                        give_up.saturating_duration_since(Instant::now())
+                    });
+                    if remaining == Duration::ZERO {
+                        return Ok(None);
+                    }
+                    thread::sleep(remaining.min(POLL_INTERVAL));
+                }
+                _ => {
+                    // In kilobytes, except on macOS.
+                    let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+                    tree.usage = Some(Usage {
+                        user: to_duration(usage.ru_utime),
+                        system: to_duration(usage.ru_stime),
+                        peak_memory: usage.ru_maxrss as u64 * unit,
+                    });
+                    return Ok(Some(exit_status(status)));
+                }
+            }
+        }
+    }
+
+    fn exit_status(status: libc::c_int) -> ExitStatus {
+        if libc::WIFEXITED(status) {
+            ExitStatus::Exited(libc::WEXITSTATUS(status) as u32)
+        } else if libc::WIFSIGNALED(status) {
+            ExitStatus::Signaled(libc::WTERMSIG(status) as u8)
+        } else {
+            ExitStatus::Other(status)
+        }
+    }
+
+    pub fn usage(tree: &ProcessTree) -> Option<Usage> {
+        tree.usage
+    }
+
+    pub fn release(tree: &ProcessTree) {
+        if tree.foreground {
+            unsafe {
+                libc::tcsetpgrp(0, libc::getpgrp());
+            }
+        }
+    }
+
+    pub fn with_limits(limits: &Limits, argv: Vec<String>) -> Vec<String> {
+        let mut ulimits = vec![];
+        if let Some(memory) = limits.memory {
+            ulimits.push(format!("ulimit -S -v {}", (memory / 1024).max(1)));
+        }
+        if let Some(cpu_time) = limits.cpu_time {
+            ulimits.push(format!("ulimit -S -t {}", cpu_time.as_secs().max(1)));
+        }
+        if ulimits.is_empty() {
+            return argv;
+        }
+        ulimits.push("exec \"$@\"".to_string());
+        let mut limited = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            ulimits.join(" && "),
+            "cargo-cmd".to_string(),
+        ];
+        limited.extend(argv);
+        limited
+    }
+
+    pub fn set_limits(_: usize, _: &Limits) -> Result<(), ()> {
+        Ok(())
+    }
+
+    // Processes inherit the niceness, so setting it for the group covers
+    // everything the command starts from here on.
+    pub fn set_niceness(id: usize, niceness: i32) -> Result<(), ()> {
+        match unsafe { libc::setpriority(libc::PRIO_PGRP, id as libc::id_t, niceness) } {
+            0 => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    fn signal(id: usize, signal: libc::c_int) {
+        unsafe {
+            libc::killpg(id as libc::pid_t, signal);
+        }
+    }
+
+    pub fn interrupt(id: usize) {
+        signal(id, libc::SIGINT);
+    }
+
+    pub fn terminate(id: usize) {
+        signal(id, libc::SIGTERM);
+    }
+
+    pub fn kill(id: usize) {
+        signal(id, libc::SIGKILL);
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        pid != 0 && unsafe { libc::kill(pid as libc::pid_t, 0) } == 0
+    }
+
+    // Background commands are started in a process group of their own.
+    pub fn stop(pid: u32) {
+        terminate(pid as usize);
+    }
+
+    pub fn force_stop(pid: u32) {
+        kill(pid as usize);
+    }
+}
+
+#[cfg(windows)]
+mod os {
+    use super::{Limits, ProcessTree, Usage};
+    use std::time::Duration;
+    use std::{mem, ptr};
+    use subprocess::{ExitStatus, Popen};
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::jobapi2::{
+        AssignProcessToJobObject, CreateJobObjectW, QueryInformationJobObject,
+        SetInformationJobObject, TerminateJobObject,
+    };
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess, TerminateProcess};
+    use winapi::um::winbase::{
+        ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
+        IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+    };
+    use winapi::um::winnt::{
+        JobObjectBasicAccountingInformation, JobObjectExtendedLimitInformation, HANDLE,
+        JOBOBJECT_BASIC_ACCOUNTING_INFORMATION, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_PRIORITY_CLASS, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+        JOB_OBJECT_LIMIT_PROCESS_TIME, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_QUOTA,
+        PROCESS_TERMINATE,
+    };
+
+    pub fn attach(pid: u32, _: bool) -> ProcessTree {
+        // Processes started by the command after this point are added to the
+        // job automatically.
+        let job = unsafe {
+            let job = CreateJobObjectW(ptr::null_mut(), ptr::null());
+            let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+            if !job.is_null() && !process.is_null() {
+                AssignProcessToJobObject(job, process);
+            }
+            if !process.is_null() {
+                CloseHandle(process);
+            }
+            job
+        };
+        ProcessTree { id: job as usize }
+    }
+
+    pub fn release(tree: &ProcessTree) {
+        if tree.id != 0 {
+            unsafe {
+                CloseHandle(tree.id as HANDLE);
+            }
+        }
+    }
+
+    // Update the limits that apply to every process in the job.
+    fn update_limits<F>(id: usize, update: F) -> Result<(), ()>
+    where
+        F: FnOnce(&mut JOBOBJECT_EXTENDED_LIMIT_INFORMATION),
+    {
+        unsafe {
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = mem::zeroed();
+            let size = mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as DWORD;
+            let info_ptr = &mut info as *mut _ as *mut _;
+            if QueryInformationJobObject(
+                id as HANDLE,
+                JobObjectExtendedLimitInformation,
+                info_ptr,
+                size,
+                ptr::null_mut(),
+            ) == 0
+            {
+                return Err(());
+            }
+            update(&mut info);
+            match SetInformationJobObject(
+                id as HANDLE,
+                JobObjectExtendedLimitInformation,
+                info_ptr,
+                size,
+            ) {
+                0 => Err(()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    pub fn with_limits(_: &Limits, argv: Vec<String>) -> Vec<String> {
+        argv
+    }
+
+    pub fn wait(
+        _: &mut ProcessTree,
+        process: &mut Popen,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ExitStatus>, ()> {
+        match timeout {
+            Some(timeout) => process.wait_timeout(timeout).or(Err(())),
+            None => process.wait().map(Some).or(Err(())),
+        }
+    }
+
+    // The job keeps count for all of its processes.
+    pub fn usage(tree: &ProcessTree) -> Option<Usage> {
+        unsafe {
+            let mut accounting: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = mem::zeroed();
+            let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = mem::zeroed();
+            if QueryInformationJobObject(
+                tree.id as HANDLE,
+                JobObjectBasicAccountingInformation,
+                &mut accounting as *mut _ as *mut _,
+                mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as DWORD,
+                ptr::null_mut(),
+            ) == 0
+                || QueryInformationJobObject(
+                    tree.id as HANDLE,
+                    JobObjectExtendedLimitInformation,
+                    &mut limits as *mut _ as *mut _,
+                    mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as DWORD,
+                    ptr::null_mut(),
+                ) == 0
+            {
+                return None;
+            }
+            // In units of 100 nanoseconds.
+            let to_duration = |time: i64| Duration::from_nanos(time as u64 * 100);
+            Some(Usage {
+                user: to_duration(*accounting.TotalUserTime.QuadPart()),
+                system: to_duration(*accounting.TotalKernelTime.QuadPart()),
+                peak_memory: limits.PeakProcessMemoryUsed as u64,
+            })
+        }
+    }
+
+    pub fn set_limits(id: usize, limits: &Limits) -> Result<(), ()> {
+        if limits.memory.is_none() && limits.cpu_time.is_none() {
+            return Ok(());
+        }
+        update_limits(id, |info| {
+            if let Some(memory) = limits.memory {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = memory as usize;
+            }
+            if let Some(cpu_time) = limits.cpu_time {
+                // In units of 100 nanoseconds.
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                unsafe {
+                    *info
+                        .BasicLimitInformation
+                        .PerProcessUserTimeLimit
+                        .QuadPart_mut() = (cpu_time.as_nanos() / 100) as i64;
+                }
+            }
+        })
+    }
+
+    pub fn set_niceness(id: usize, niceness: i32) -> Result<(), ()> {
+        let class = match niceness {
+            n if n >= 15 => IDLE_PRIORITY_CLASS,
+            n if n >= 5 => BELOW_NORMAL_PRIORITY_CLASS,
+            n if n > -5 => NORMAL_PRIORITY_CLASS,
+            n if n > -15 => ABOVE_NORMAL_PRIORITY_CLASS,
+            _ => HIGH_PRIORITY_CLASS,
+        };
+        update_limits(id, |info| {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PRIORITY_CLASS;
+            info.BasicLimitInformation.PriorityClass = class;
+        })
+    }
+
+    pub fn interrupt(id: usize) {
+        kill(id);
+    }
+
+    pub fn terminate(id: usize) {
+        kill(id);
+    }
+
+    pub fn kill(id: usize) {
+        if id != 0 {
+            unsafe {
+                TerminateJobObject(id as HANDLE, 1);
+            }
+        }
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return false;
+            }
+            let mut exit_code: DWORD = 0;
+            let running =
+                GetExitCodeProcess(process, &mut exit_code) != 0 && exit_code == STILL_ACTIVE;
+            CloseHandle(process);
+            running
+        }
+    }
+
+    // Background commands aren't kept in a job, so only the process itself is
+    // stopped.
+    pub fn stop(pid: u32) {
+        unsafe {
+            let process = OpenProcess(PROCESS_TERMINATE, 0, pid);
+            if !process.is_null() {
+                TerminateProcess(process, 1);
+                CloseHandle(process);
+            }
+        }
+    }
+
+    // `stop` already terminates the process.
+    pub fn force_stop(pid: u32) {
+        stop(pid);
+    }
+}
@@ -0,0 +1,86 @@
+// Joining the arguments passed after the command onto it. On Unix they're
+// added as they are, so they can use the shell like the command itself. On
+// Windows each one is quoted so cmd.exe passes it on as a single argument,
+// which matters for paths like `C:\Program Files\...`. Arguments cargo-cmd
+// puts together itself, like the `args` of cargo-make tasks, are always quoted.
+
+// Characters cmd.exe gives a meaning to, even inside double quotes.
+#[cfg(windows)]
+const CMD_SPECIAL: &str = "()%!^\"<>&|";
+
+#[cfg(unix)]
+pub fn join(args: &[String]) -> String {
+    args.join(" ")
+}
+
+// `arg` quoted for the shell commands run in, so it's passed on as a single
+// argument whatever it contains.
+#[cfg(unix)]
+pub fn quote(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(windows)]
+pub fn quote(arg: &str) -> String {
+    cmd_exe(arg)
+}
+
+#[cfg(windows)]
+pub fn join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| cmd_exe(arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Quote `arg` the way programs split their command line, then escape what
+// cmd.exe would otherwise interpret with `^`.
+#[cfg(windows)]
+fn cmd_exe(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || CMD_SPECIAL.contains(c));
+    if !needs_quotes {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            // Backslashes only escape when they come before a quote.
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    let mut escaped = String::new();
+    for c in quoted.chars() {
+        if CMD_SPECIAL.contains(c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,237 @@
+use ci;
+use command::Command;
+use exec;
+use std::cmp::Ordering;
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use subprocess::{Exec, ExitStatus, NullFile, Redirection};
+use term;
+use version;
+
+const OPERATORS: [&str; 6] = [">=", "<=", "==", ">", "<", "="];
+
+// A tool a command needs, like `node` or `docker >= 24`.
+struct Requirement<'a> {
+    tool: &'a str,
+    constraint: Option<(&'a str, &'a str)>,
+}
+
+impl<'a> Requirement<'a> {
+    fn parse(requirement: &'a str) -> Result<Requirement<'a>, String> {
+        let invalid = || format!("Invalid requirement \"{}\"", requirement);
+        let mut parts = requirement.split_whitespace();
+        let tool = parts.next().ok_or_else(invalid)?;
+        let constraint = match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => None,
+            (Some(operator), Some(version), None) if OPERATORS.contains(&operator) => {
+                Some((operator, version))
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Requirement { tool, constraint })
+    }
+
+    // Cargo subcommands are crates of the same name, so they can be installed
+    // with `cargo install`.
+    fn install_args(&self) -> Option<Vec<&'a str>> {
+        if !self.tool.starts_with("cargo-") {
+            return None;
+        }
+        let mut args = vec!["install", self.tool];
+        match self.constraint {
+            Some(("=", version)) | Some(("==", version)) => {
+                args.extend(&["--version", version]);
+            }
+            Some((">=", _)) | Some((">", _)) | None => args.push("--force"),
+            Some(_) => return None,
+        }
+        Some(args)
+    }
+
+    // Why the tool doesn't meet the requirement, if it doesn't.
+    fn problem(&self, search_path: Option<&OsStr>) -> Option<String> {
+        let path = match exec::find_program(self.tool, search_path) {
+            Some(path) => path,
+            None => return Some("not found".to_string()),
+        };
+        let (operator, wanted) = self.constraint?;
+        let found = match installed_version(&path) {
+            Some(found) => found,
+            None => return Some("could not determine its version".to_string()),
+        };
+        let ordering = version::compare(&version::parse(&found), &version::parse(wanted));
+        let satisfied = match operator {
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            "<" => ordering == Ordering::Less,
+            _ => ordering == Ordering::Equal,
+        };
+        if satisfied {
+            None
+        } else {
+            Some(format!("found {}", found))
+        }
+    }
+}
+
+// The toolchains rustup has installed, or `None` without rustup.
+fn installed_toolchains() -> Option<Vec<String>> {
+    let capture = Exec::cmd("rustup")
+        .args(&["toolchain", "list"])
+        .stdout(Redirection::Pipe)
+        .stderr(NullFile)
+        .capture()
+        .ok()?;
+    if !capture.success() {
+        return None;
+    }
+    Some(
+        capture
+            .stdout_str()
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+// Whether `toolchain`, like `nightly` or `1.80`, is the installed `name`, which
+// has the host's target triple after it. `nightly` isn't a dated nightly.
+fn is_toolchain(name: &str, toolchain: &str) -> bool {
+    name == toolchain
+        || name.strip_prefix(toolchain).is_some_and(|triple| {
+            triple.starts_with('-') && !triple[1..].starts_with(|c: char| c.is_ascii_digit())
+        })
+}
+
+// Check that the toolchain in the command's `toolchain` is installed, which is
+// done with `rustup toolchain install` when `auto_install` is set or the user
+// agrees to it.
+fn check_toolchain(toolchain: &str, auto_install: bool) -> Option<String> {
+    let installed = match installed_toolchains() {
+        Some(installed) => installed,
+        None => return Some("rustup not found".to_string()),
+    };
+    if installed.iter().any(|name| is_toolchain(name, toolchain)) {
+        return None;
+    }
+    let install = format!("rustup toolchain install {}", toolchain);
+    if auto_install
+        || confirm(&format!(
+            "Toolchain {} is not installed, run `{}`?",
+            toolchain, install
+        ))
+    {
+        term::status(&format!("> {}", install));
+        match Exec::cmd("rustup")
+            .args(&["toolchain", "install", toolchain])
+            .join()
+        {
+            Ok(ExitStatus::Exited(0)) => None,
+            _ => Some("could not install it".to_string()),
+        }
+    } else {
+        Some(format!("not installed, install it with `{}`", install))
+    }
+}
+
+// Check that the tools every command declares in `requires` are installed,
+// along with the toolchains in `toolchain`, before running any of them.
+// Missing cargo subcommands and toolchains are installed when `auto_install`
+// is set or the user agrees to it, the other missing tools are reported
+// together.
+pub fn check<'a, I>(commands: I, auto_install: bool) -> Result<(), String>
+where
+    I: IntoIterator<Item = &'a Command>,
+{
+    let commands: Vec<&Command> = commands.into_iter().collect();
+    let mut checked: Vec<&str> = vec![];
+    let mut toolchains: Vec<&str> = vec![];
+    let mut missing = vec![];
+    for toolchain in commands
+        .iter()
+        .filter_map(|command| command.options.toolchain.as_deref())
+    {
+        if toolchains.contains(&toolchain) {
+            continue;
+        }
+        toolchains.push(toolchain);
+        if let Some(problem) = check_toolchain(toolchain, auto_install) {
+            missing.push(format!("    toolchain {} ({})", toolchain, problem));
+        }
+    }
+    let requirements = commands.into_iter().flat_map(|command| {
+        let path = exec::search_path(command, env::var_os("PATH").as_deref());
+        command
+            .options
+            .requires
+            .iter()
+            .map(move |requirement| (requirement, path.clone()))
+    });
+    for (requirement, path) in requirements {
+        if checked.contains(&&requirement[..]) {
+            continue;
+        }
+        checked.push(requirement);
+        let path = path?;
+        let parsed = Requirement::parse(requirement)?;
+        let mut problem = parsed.problem(path.as_deref());
+        if let (Some(ref reason), Some(args)) = (&problem, parsed.install_args()) {
+            let install = format!("cargo {}", args.join(" "));
+            if auto_install
+                || confirm(&format!(
+                    "{} is required ({}), run `{}`?",
+                    requirement, reason, install
+                ))
+            {
+                term::status(&format!("> {}", install));
+                match Exec::cmd(exec::cargo()).args(&args).join() {
+                    Ok(ExitStatus::Exited(0)) => {}
+                    _ => return Err(format!("Could not install {}", parsed.tool)),
+                }
+                problem = parsed.problem(path.as_deref());
+            } else {
+                problem = Some(format!("{}, install it with `{}`", reason, install));
+            }
+        }
+        if let Some(problem) = problem {
+            missing.push(format!("    {} ({})", requirement, problem));
+        }
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Missing required tools:\n{}", missing.join("\n")))
+    }
+}
+
+// Ask a yes or no question, which is a no when nobody is there to answer.
+fn confirm(question: &str) -> bool {
+    if !ci::interactive() {
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok();
+    ["y", "yes"].contains(&&answer.trim().to_lowercase()[..])
+}
+
+// The first version number in the tool's `--version` output.
+fn installed_version(path: &Path) -> Option<String> {
+    let capture = Exec::cmd(path)
+        .arg("--version")
+        .stdout(Redirection::Pipe)
+        .stderr(NullFile)
+        .capture()
+        .ok()?;
+    capture
+        .stdout_str()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))
+        .map(str::to_string)
+}
@@ -0,0 +1,92 @@
+// Commands with `script` run a Rhai script instead of a shell command, for
+// logic that doesn't fit on one line. Scripts get the `[package]` table as
+// `project`, the arguments as `args` and the parameters as `params`, and
+// `run("...")` runs a shell command the way the command itself would, with
+// its limits, deadline and output, and returns its exit code. The script's
+// exit code is what it evaluates to, if that's a number.
+
+use command::Command;
+use exec::{self, Invocation, SHELL};
+use manifest;
+use process_tree;
+use rhai::{serde::to_dynamic, Array, Dynamic, Engine, EvalAltResult, Map, Position, Scope};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use subprocess::ExitStatus;
+use term;
+
+fn engine(command: &Command, invocation: &Invocation, env: Vec<(OsString, OsString)>) -> Engine {
+    let mut engine = Engine::new();
+    let deadline = invocation.deadline;
+    let mut command = command.clone();
+    command.options.script = false;
+    command.options.job = None;
+    let invocation = invocation.clone();
+    engine.register_fn("run", move |cmd: &str| -> Result<i64, Box<EvalAltResult>> {
+        term::status(&format!("> {}", cmd));
+        let argv = [SHELL[0], SHELL[1], cmd];
+        match exec::spawn_and_wait(&command, cmd, &argv, env.clone(), &invocation) {
+            Ok(Some(_)) if process_tree::interrupted() => Err(terminated()),
+            Ok(Some(ExitStatus::Exited(exit_code))) => Ok(exit_code as i64),
+            Ok(Some(_)) => Ok(1),
+            // The deadline passed.
+            Ok(None) => Err(terminated()),
+            Err(error_msg) => Err(error_msg.into()),
+        }
+    });
+    // Stop the script when it's interrupted or runs out of time.
+    engine.on_progress(move |_| {
+        let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if timed_out || process_tree::interrupted() {
+            Some(Dynamic::UNIT)
+        } else {
+            None
+        }
+    });
+    engine
+}
+
+// Stops the script the way `on_progress` does.
+fn terminated() -> Box<EvalAltResult> {
+    EvalAltResult::ErrorTerminated(Dynamic::UNIT, Position::NONE).into()
+}
+
+// Run the script of `command`. Returns `None` if the invocation's deadline
+// passed first.
+pub fn run(
+    command: &Command,
+    invocation: &Invocation,
+    env: Vec<(OsString, OsString)>,
+) -> Result<Option<ExitStatus>, String> {
+    let path = &command.cmd;
+    let source = fs::read_to_string(path).or(Err(format!("Could not find or open {}", path)))?;
+    let package = manifest::package(Path::new("."))
+        .and_then(|package| to_dynamic(package).ok())
+        .unwrap_or_else(|| Map::new().into());
+    let args: Array = invocation.rest.iter().cloned().map(Dynamic::from).collect();
+    let params: Map = invocation
+        .values
+        .iter()
+        .map(|(name, value)| (name.into(), value.into()))
+        .collect();
+
+    let mut scope = Scope::new();
+    scope.push_constant("project", package);
+    scope.push_constant("args", args);
+    scope.push_constant("params", params);
+
+    term::status(&format!("> {}", path));
+    let engine = engine(command, invocation, env);
+    match engine.eval_with_scope::<Dynamic>(&mut scope, &source) {
+        Ok(result) => Ok(Some(ExitStatus::Exited(
+            result.as_int().map_or(0, |code| code as u32),
+        ))),
+        Err(error) => match *error {
+            EvalAltResult::ErrorTerminated(..) if !process_tree::interrupted() => Ok(None),
+            EvalAltResult::ErrorTerminated(..) => Ok(Some(ExitStatus::Exited(1))),
+            error => Err(format!("Script {} failed: {}", path, error)),
+        },
+    }
+}
@@ -0,0 +1,51 @@
+use exec;
+use std::cmp::Ordering;
+use subprocess::{Exec, ExitStatus, NullFile, Redirection};
+use version;
+
+const NAME: &str = "cargo-cmd";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// The newest version on crates.io, as reported by `cargo search`.
+fn latest_version() -> Result<String, String> {
+    let capture = Exec::cmd(exec::cargo())
+        .args(&["search", NAME, "--limit", "1"])
+        .stdout(Redirection::Pipe)
+        .stderr(NullFile)
+        .capture()
+        .or(Err("Could not run cargo search"))?;
+    let prefix = format!("{} = \"", NAME);
+    capture
+        .stdout_str()
+        .lines()
+        .filter_map(|line| line.strip_prefix(&prefix[..]))
+        .filter_map(|rest| rest.split('"').next())
+        .map(str::to_string)
+        .next()
+        .ok_or(format!("Could not find {} on crates.io", NAME))
+}
+
+// Check crates.io for a newer version and install it unless `check_only` is
+// set, in which case being out of date is an error so CI can catch it.
+pub fn run(check_only: bool) -> Result<(), String> {
+    let latest = latest_version()?;
+    if version::compare(&version::parse(&latest), &version::parse(VERSION)) != Ordering::Greater {
+        println!("{} {} is up to date", NAME, VERSION);
+        return Ok(());
+    }
+    if check_only {
+        return Err(format!(
+            "{} {} is available, {} is installed",
+            NAME, latest, VERSION
+        ));
+    }
+
+    println!("Updating {} from {} to {}", NAME, VERSION, latest);
+    match Exec::cmd(exec::cargo())
+        .args(&["install", NAME, "--force", "--version", &latest[..]])
+        .join()
+    {
+        Ok(ExitStatus::Exited(0)) => Ok(()),
+        _ => Err(format!("Could not install {} {}", NAME, latest)),
+    }
+}
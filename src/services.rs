@@ -0,0 +1,405 @@
+// Groups of long-running commands under `services`, like a web server and a
+// worker for `cargo cmd dev`, started together with their output interleaved
+// line by line behind their names. Services that exit are restarted if their
+// `restart` says so, and Ctrl-C stops all of them. A service with `depends_on`
+// is only started once the services it names are ready. `${port(auto)}` in a
+// service's command is replaced with a free port, which all the services in
+// the group get in CARGO_CMD_PORT_<NAME>.
+
+use command::{Command, Restart};
+use duration;
+use exec::{self, Invocation};
+use logs::Log;
+use process_tree;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use subprocess::{ExitStatus, Popen};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long to wait before restarting a service, so one that keeps crashing
+// doesn't take over.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_STARTUP_TIMEOUT: &str = "30s";
+const AUTO_PORT: &str = "port(auto)";
+
+// A log both output streams of a service can write to.
+type SharedLog = Arc<Mutex<Log>>;
+
+#[derive(PartialEq)]
+enum State {
+    // Not started yet, its dependencies aren't ready.
+    Waiting,
+    // Started at the given time but not ready yet.
+    Starting(Instant),
+    Ready,
+    Exited,
+}
+
+struct Service<'a> {
+    name: &'a str,
+    command: &'a Command,
+    prefix: String,
+    // The invocation with the ports of the group.
+    invocation: Invocation,
+    process: Option<Popen>,
+    state: State,
+    // Set once the service has printed its `wait_for_log`.
+    logged: Arc<AtomicBool>,
+    // Where its output goes if it isn't shown, see `logs`.
+    stdout_log: Option<SharedLog>,
+    stderr_log: Option<SharedLog>,
+    // The threads printing its output.
+    forwarding: Vec<JoinHandle<()>>,
+}
+
+// Print each line `output` gives behind the name of the service, or write it
+// to its log, and note when one of them contains `wait_for_log`.
+fn forward<R: Read + Send + 'static>(
+    service: &Service,
+    output: Option<R>,
+    log: Option<SharedLog>,
+) -> Option<JoinHandle<()>> {
+    let prefix = service.prefix.clone();
+    let wait_for_log = service.command.options.wait_for_log.clone();
+    let logged = service.logged.clone();
+    output.map(|output| {
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                match log {
+                    Some(ref log) => log.lock().unwrap().write_line(&line),
+                    None => println!("{} | {}", prefix, line),
+                }
+                if wait_for_log.as_ref().is_some_and(|log| line.contains(log)) {
+                    logged.store(true, Ordering::SeqCst);
+                }
+            }
+        })
+    })
+}
+
+fn start(service: &mut Service) -> Result<(), String> {
+    let mut process = exec::spawn_piped(service.command, &service.invocation)?;
+    service.logged.store(false, Ordering::SeqCst);
+    let stdout = forward(service, process.stdout.take(), service.stdout_log.clone());
+    let stderr = forward(service, process.stderr.take(), service.stderr_log.clone());
+    service.forwarding = stdout.into_iter().chain(stderr).collect();
+    service.process = Some(process);
+    service.state = State::Starting(Instant::now());
+    Ok(())
+}
+
+// Whether a started service passes the checks it has for being ready.
+fn is_ready(service: &Service) -> bool {
+    let options = &service.command.options;
+    let logged = options.wait_for_log.is_none() || service.logged.load(Ordering::SeqCst);
+    let listening = options.wait_for_port.is_none_or(|port| {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        TcpStream::connect_timeout(&address, POLL_INTERVAL).is_ok()
+    });
+    logged && listening
+}
+
+// Give the output a service printed before it exited a moment to come out
+// first. Processes it left behind can keep its output open, so this doesn't
+// wait for the end of it.
+fn flush(service: &mut Service) {
+    let started = Instant::now();
+    while service
+        .forwarding
+        .iter()
+        .any(|thread| !thread.is_finished())
+        && started.elapsed() < FLUSH_TIMEOUT
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    service.forwarding.clear();
+}
+
+// The logs for the service's output in `stdout_file` and `stderr_file`, opened
+// once so restarts keep writing to them. The same file for both streams is
+// shared.
+fn open_logs(command: &Command) -> Result<(Option<SharedLog>, Option<SharedLog>), String> {
+    let options = &command.options;
+    let open = |path: &String| {
+        Log::open(
+            path,
+            options.append,
+            options.log_rotate.as_ref(),
+            options.log_keep,
+        )
+        .map(|log| Arc::new(Mutex::new(log)))
+    };
+    let stdout = options.stdout_file.as_ref().map(open).transpose()?;
+    let stderr = match options.stderr_file {
+        Some(ref path) if options.stdout_file.as_ref() == Some(path) => stdout.clone(),
+        Some(ref path) => Some(open(path)?),
+        None => None,
+    };
+    Ok((stdout, stderr))
+}
+
+// Environment variable the port picked for a service is exposed as.
+fn port_env_name(name: &str) -> String {
+    format!(
+        "CARGO_CMD_PORT_{}",
+        name.to_uppercase()
+            .replace(|c: char| !c.is_alphanumeric(), "_")
+    )
+}
+
+// A port on localhost that nothing is listening on right now.
+fn free_port() -> Result<u16, String> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .or(Err("Could not find a free port".to_string()))
+}
+
+// Pick a port for each service that asks for one with `${port(auto)}`.
+fn ports(services: &BTreeMap<String, Command>) -> Result<BTreeMap<&str, u16>, String> {
+    let mut ports = BTreeMap::new();
+    for (name, command) in services {
+        if command.cmd.contains(&format!("${{{}}}", AUTO_PORT)) {
+            let mut port = free_port()?;
+            // The listener is gone once we know the port, so the same one
+            // can come up again.
+            while ports.values().any(|&other| other == port) {
+                port = free_port()?;
+            }
+            ports.insert(&name[..], port);
+        }
+    }
+    Ok(ports)
+}
+
+// Ask every service to stop, and kill the ones still running once the grace
+// period is over, like commands that run out of time.
+fn stop(services: &mut [Service]) {
+    let mut processes: Vec<Popen> = services
+        .iter_mut()
+        .filter_map(|service| service.process.take())
+        .collect();
+    for pid in processes.iter().filter_map(Popen::pid) {
+        process_tree::stop(pid);
+    }
+    let deadline = Instant::now() + exec::GRACE_PERIOD;
+    for process in processes.iter_mut() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if let Ok(None) = process.wait_timeout(left) {
+            if let Some(pid) = process.pid() {
+                process_tree::force_stop(pid);
+            }
+            process.wait().ok();
+        }
+    }
+}
+
+// Make sure every service can be started: the ones in `depends_on` have to be
+// in the group and can't depend on it in turn.
+fn check_dependencies(services: &BTreeMap<String, Command>) -> Result<(), String> {
+    for (name, command) in services {
+        for dependency in &command.options.depends_on {
+            if !services.contains_key(dependency) {
+                return Err(format!(
+                    "Service \"{}\" depends on \"{}\", which is not in its group",
+                    name, dependency
+                ));
+            }
+        }
+    }
+    let mut startable: Vec<&String> = vec![];
+    loop {
+        let next: Vec<&String> = services
+            .iter()
+            .filter(|(name, command)| {
+                !startable.contains(name)
+                    && command
+                        .options
+                        .depends_on
+                        .iter()
+                        .all(|dependency| startable.contains(&dependency))
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if next.is_empty() {
+            break;
+        }
+        startable.extend(next);
+    }
+    let stuck: Vec<&str> = services
+        .keys()
+        .filter(|name| !startable.contains(name))
+        .map(|name| &name[..])
+        .collect();
+    if !stuck.is_empty() {
+        return Err(format!(
+            "Services \"{}\" depend on each other",
+            stuck.join("\", \"")
+        ));
+    }
+    Ok(())
+}
+
+// Move the services along: start the ones whose dependencies are ready, and
+// note the ones that have become ready.
+fn advance(services: &mut [Service]) -> Result<(), String> {
+    for index in 0..services.len() {
+        match services[index].state {
+            State::Waiting => {
+                let depends_on = &services[index].command.options.depends_on;
+                let ready = depends_on.iter().all(|dependency| {
+                    services
+                        .iter()
+                        .any(|other| other.name == dependency && other.state == State::Ready)
+                });
+                let gone = depends_on.iter().find(|dependency| {
+                    services
+                        .iter()
+                        .any(|other| other.name == *dependency && other.state == State::Exited)
+                });
+                if let Some(dependency) = gone {
+                    return Err(format!(
+                        "Service \"{}\" could not start, \"{}\" exited before it was ready",
+                        services[index].name, dependency
+                    ));
+                }
+                if ready {
+                    start(&mut services[index])?;
+                }
+            }
+            State::Starting(started) => {
+                let service = &mut services[index];
+                if is_ready(service) {
+                    if service.command.options.wait_for_log.is_some()
+                        || service.command.options.wait_for_port.is_some()
+                    {
+                        println!("{} | ready", service.prefix);
+                    }
+                    service.state = State::Ready;
+                    continue;
+                }
+                let timeout = service
+                    .command
+                    .options
+                    .startup_timeout
+                    .as_ref()
+                    .map_or(DEFAULT_STARTUP_TIMEOUT, |timeout| &timeout[..]);
+                if started.elapsed() >= duration::parse(timeout)? {
+                    return Err(format!(
+                        "Service \"{}\" was not ready within {}",
+                        service.name, timeout
+                    ));
+                }
+            }
+            State::Ready | State::Exited => {}
+        }
+    }
+    Ok(())
+}
+
+// Run the services until they've all exited for good, or until the run is
+// interrupted. Returns the exit code of the first one that failed.
+pub fn run(services: &BTreeMap<String, Command>, invocation: &Invocation) -> Result<i32, String> {
+    check_dependencies(services)?;
+    let ports = ports(services)?;
+    let mut group = invocation.clone();
+    group.env.extend(
+        ports
+            .iter()
+            .map(|(name, port)| (port_env_name(name), port.to_string())),
+    );
+    let width = services.keys().map(String::len).max().unwrap_or(0);
+    let mut services: Vec<Service> = services
+        .iter()
+        .map(|(name, command)| {
+            let (stdout_log, stderr_log) = open_logs(command)?;
+            Ok(Service {
+                name,
+                command,
+                prefix: format!("{:width$}", name, width = width),
+                invocation: {
+                    let mut invocation = group.clone();
+                    if let Some(port) = ports.get(&name[..]) {
+                        invocation
+                            .placeholders
+                            .insert(AUTO_PORT.to_string(), port.to_string());
+                    }
+                    invocation
+                },
+                process: None,
+                state: State::Waiting,
+                logged: Arc::new(AtomicBool::new(false)),
+                stdout_log,
+                stderr_log,
+                forwarding: vec![],
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    for service in &services {
+        if let Some(port) = ports.get(service.name) {
+            println!("{} | port {}", service.prefix, port);
+        }
+    }
+    process_tree::install_interrupt_handler();
+
+    let mut exit_code = 0;
+    while services
+        .iter()
+        .any(|service| service.process.is_some() || service.state == State::Waiting)
+    {
+        if let Err(error_msg) = advance(&mut services) {
+            stop(&mut services);
+            return Err(error_msg);
+        }
+        if process_tree::interrupted() {
+            stop(&mut services);
+            return Ok(1);
+        }
+        thread::sleep(POLL_INTERVAL);
+        for service in services.iter_mut() {
+            let status = match service.process.as_mut().and_then(Popen::poll) {
+                Some(status) => status,
+                None => continue,
+            };
+            service.process = None;
+            flush(service);
+            let code = match status {
+                ExitStatus::Exited(code) => code as i32,
+                _ => 1,
+            };
+            let restart = match service.command.options.restart.unwrap_or_default() {
+                Restart::No => false,
+                Restart::OnFailure => code != 0,
+                Restart::Always => true,
+            };
+            if restart {
+                println!("{} | exited with {}, restarting", service.prefix, code);
+                thread::sleep(RESTART_DELAY);
+                if let Err(error_msg) = start(service) {
+                    stop(&mut services);
+                    return Err(error_msg);
+                }
+            } else {
+                println!("{} | exited with {}", service.prefix, code);
+                // A service that ran to completion counts as ready for the
+                // ones depending on it, like a migration before a server.
+                service.state = if code == 0 && (service.state == State::Ready || is_ready(service))
+                {
+                    State::Ready
+                } else {
+                    State::Exited
+                };
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+            }
+        }
+    }
+    Ok(exit_code)
+}
@@ -0,0 +1,130 @@
+// Shell aliases for the commands of a project, like `ccb` for
+// `cargo cmd build`, kept in a block of the shell's rc file that's marked
+// with the package name so it can be replaced or removed later.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The rc file of the shell in $SHELL. fish reads the same `alias` lines as
+// bash and zsh.
+fn rc_path() -> Result<PathBuf, String> {
+    let home = env::var_os("HOME").ok_or("Could not find the home directory, HOME is not set")?;
+    let home = Path::new(&home);
+    let shell = env::var("SHELL").unwrap_or_default();
+    match shell.rsplit('/').next().unwrap_or_default() {
+        "bash" => Ok(home.join(".bashrc")),
+        "zsh" => Ok(home.join(".zshrc")),
+        "fish" => Ok(home.join(".config/fish/config.fish")),
+        _ => Err(format!(
+            "Could not add aliases for shell `{}`, only bash, zsh and fish are supported",
+            shell
+        )),
+    }
+}
+
+fn markers(package: &str) -> (String, String) {
+    (
+        format!("# >>> cargo-cmd aliases for {} >>>", package),
+        format!("# <<< cargo-cmd aliases for {} <<<", package),
+    )
+}
+
+// `cc` and the first letter of each word of the name.
+fn alias_for(name: &str) -> String {
+    let initials: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(|word| word.chars().next())
+        .collect();
+    format!("cc{}", initials.to_ascii_lowercase())
+}
+
+// The rc file without the block of `package`, or the blank lines it ends
+// with, which `install` adds one of before the block.
+fn without_block(contents: &str, package: &str) -> String {
+    let (begin, end) = markers(package);
+    let mut inside = false;
+    let mut lines = vec![];
+    for line in contents.lines() {
+        if line == begin {
+            inside = true;
+        } else if line == end {
+            inside = false;
+        } else if !inside {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    contents
+}
+
+// Add an alias for each command, replacing the ones added for the package
+// before. When two commands would get the same alias, the first one by name
+// gets it.
+pub fn install<T>(commands: &HashMap<String, T>, package: &str) -> Result<(), String> {
+    let path = rc_path()?;
+    let contents = fs::read_to_string(&path).unwrap_or_default();
+
+    let mut names: Vec<&String> = commands.keys().filter(|name| !name.contains('*')).collect();
+    names.sort();
+
+    let (begin, end) = markers(package);
+    let mut block = vec![begin];
+    let mut taken: HashMap<String, &String> = HashMap::new();
+    for name in names {
+        let alias = alias_for(name);
+        if let Some(other) = taken.get(&alias) {
+            println!(
+                "Skipped `{}`, `{}` is already `cargo cmd {}`",
+                name, alias, other
+            );
+            continue;
+        }
+        block.push(format!("alias {}='cargo cmd {}'", alias, name));
+        println!("Added `{}` for `cargo cmd {}`", alias, name);
+        taken.insert(alias, name);
+    }
+    block.push(end);
+
+    let mut contents = without_block(&contents, package);
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    contents.push_str(&block.join("\n"));
+    contents.push('\n');
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).or(Err(format!("Could not create {}", dir.display())))?;
+    }
+    fs::write(&path, contents).or(Err(format!("Could not write {}", path.display())))?;
+    println!("Open a new shell or source {} to use them", path.display());
+    Ok(())
+}
+
+// Remove the aliases added for the package.
+pub fn remove(package: &str) -> Result<(), String> {
+    let path = rc_path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return Ok(()),
+    };
+    let (begin, _) = markers(package);
+    if !contents.lines().any(|line| line == begin) {
+        return Ok(());
+    }
+    let contents = without_block(&contents, package);
+    fs::write(&path, contents).or(Err(format!("Could not write {}", path.display())))?;
+    println!(
+        "Removed the aliases for {} from {}",
+        package,
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+// Parse sizes like `512K`, `100MB` or `2G` into bytes, using powers of 1024.
+// A bare number is taken as bytes.
+pub fn parse(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .or(Err(format!("Invalid size \"{}\"", value)))?;
+    let unit = unit.trim().to_uppercase();
+    let multiplier: u64 = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "Invalid size \"{}\", expected a unit of K, M, G or T",
+                value
+            ))
+        }
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or(format!("Invalid size \"{}\", it's too big", value))
+}
+
+// Format bytes for people, like `45.3M`, in the units `parse` takes.
+pub fn format(bytes: u64) -> String {
+    let units = [
+        ("T", 1u64 << 40),
+        ("G", 1 << 30),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+    ];
+    match units.iter().find(|unit| bytes >= unit.1) {
+        Some((unit, size)) => format!("{:.1}{}", bytes as f64 / *size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
@@ -0,0 +1,84 @@
+// What to say when a command isn't found: the hooks that are defined for it,
+// which usually means its name has a typo, and the commands with similar names.
+
+use command::{self, Command};
+use std::collections::HashMap;
+
+// How many single character edits, or swaps of neighbouring characters,
+// turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+pub fn not_found(commands: &HashMap<String, Command>, name: &str) -> String {
+    let mut error_msg = format!("Command \"{}\" not found in Cargo.toml", name);
+    let hooks: Vec<String> = vec![format!("pre{}", name), format!("post{}", name)]
+        .into_iter()
+        .filter(|hook| !command::steps(commands, hook).is_empty())
+        .map(|hook| format!("\"{}\"", hook))
+        .collect();
+    if !hooks.is_empty() {
+        error_msg.push_str(&format!(
+            "\n    {} {} defined, but not \"{}\" itself",
+            hooks.join(" and "),
+            if hooks.len() > 1 { "are" } else { "is" },
+            name
+        ));
+    }
+
+    let similar = similar(commands, name);
+    if !similar.is_empty() {
+        let names: Vec<String> = similar
+            .iter()
+            .map(|command| format!("\"{}\"", command))
+            .collect();
+        error_msg.push_str(&format!("\n    Did you mean {}?", names.join(" or ")));
+    }
+    error_msg
+}
+
+// Up to three commands with names close to `name`, closest first.
+pub fn similar<'a>(commands: &'a HashMap<String, Command>, name: &str) -> Vec<&'a str> {
+    // Commands that only have hooks are suggested too, as their hooks being
+    // there means they were meant to exist.
+    let mut similar: Vec<(usize, &str)> = commands
+        .iter()
+        .map(|(name, command)| command::base_name(name, command))
+        .flat_map(|command| {
+            let hooked = ["pre", "post"]
+                .iter()
+                .filter_map(move |prefix| command.strip_prefix(prefix))
+                .filter(|hooked| !hooked.is_empty());
+            Some(command).into_iter().chain(hooked)
+        })
+        .map(|command| (distance(name, command), command))
+        .filter(|(distance, _)| *distance > 0 && *distance <= (name.len() / 3).max(1))
+        .collect();
+    similar.sort();
+    similar.dedup();
+    similar
+        .into_iter()
+        .take(3)
+        .map(|(_, command)| command)
+        .collect()
+}
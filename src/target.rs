@@ -0,0 +1,33 @@
+use std::env;
+use subprocess::{Exec, NullFile, Redirection};
+
+// The target triple to pick commands for: the one passed with `--target`,
+// then cargo's own `CARGO_BUILD_TARGET`, and otherwise the host rustc builds
+// for by default.
+pub fn resolve(target: Option<&str>) -> Result<String, String> {
+    if let Some(target) = target {
+        return Ok(target.to_string());
+    }
+    if let Ok(target) = env::var("CARGO_BUILD_TARGET") {
+        return Ok(target);
+    }
+    host().ok_or(
+        "Could not determine the target to pick commands for, pass it with --target".to_string(),
+    )
+}
+
+fn host() -> Option<String> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let capture = Exec::cmd(rustc)
+        .arg("-vV")
+        .stdout(Redirection::Pipe)
+        .stderr(NullFile)
+        .capture()
+        .ok()?;
+    capture
+        .stdout_str()
+        .lines()
+        .filter_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+        .next()
+}
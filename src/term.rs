@@ -0,0 +1,34 @@
+// cargo's terminal settings, which cargo-cmd's own output follows too:
+// CARGO_TERM_COLOR for whether errors are colored and CARGO_TERM_QUIET for
+// whether the commands being run are printed.
+
+use clap::AppSettings;
+use std::env;
+
+pub fn quiet() -> bool {
+    env::var("CARGO_TERM_QUIET").is_ok_and(|quiet| quiet == "true")
+}
+
+// `Some` when colors are forced on or off, `None` to use them in terminals.
+pub fn color() -> Option<bool> {
+    match env::var("CARGO_TERM_COLOR").as_deref() {
+        Ok("always") => Some(true),
+        Ok("never") => Some(false),
+        _ => None,
+    }
+}
+
+pub fn clap_setting() -> AppSettings {
+    match color() {
+        Some(true) => AppSettings::ColorAlways,
+        Some(false) => AppSettings::ColorNever,
+        None => AppSettings::ColorAuto,
+    }
+}
+
+// One of cargo-cmd's own lines, like the command it's about to run.
+pub fn status(line: &str) {
+    if !quiet() {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,150 @@
+// Traces of a run in the OTLP JSON format, with a span for the invocation and
+// one for each command it ran. They are written to a file, which the
+// collector's `otlpjsonfile` receiver can read, or sent to an OTLP/HTTP
+// endpoint such as `http://localhost:4318/v1/traces`.
+
+use command::{self, Command};
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use ureq;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub struct Trace {
+    destination: String,
+    package: String,
+    command: String,
+    trace_id: String,
+    span_id: String,
+    started: SystemTime,
+    spans: Mutex<Vec<Value>>,
+}
+
+// A random id of `bytes` bytes as hex, which is how OTLP JSON encodes ids.
+fn random_id(bytes: usize) -> String {
+    (0..bytes / 8)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos())
+        .to_string()
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::Number(number) => json!({ "intValue": number.to_string() }),
+        value => json!({ "stringValue": value }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+// OTLP status codes, where 1 is OK and 2 is ERROR.
+fn status(exit_code: i32) -> Value {
+    json!({ "code": if exit_code == 0 { 1 } else { 2 } })
+}
+
+impl Trace {
+    pub fn new(destination: &str, package: &str, command: &str) -> Trace {
+        Trace {
+            destination: destination.to_string(),
+            package: package.to_string(),
+            command: command.to_string(),
+            trace_id: random_id(16),
+            span_id: random_id(8),
+            started: SystemTime::now(),
+            spans: Mutex::new(vec![]),
+        }
+    }
+
+    // Where `name` stands in the chain around the command that was asked for.
+    fn phase(&self, name: &str, command: &Command) -> &'static str {
+        let name = command::base_name(name, command);
+        match name {
+            _ if name == self.command => "main",
+            "setup" => "setup",
+            "teardown" => "teardown",
+            _ if name.starts_with("pre") => "pre",
+            _ => "post",
+        }
+    }
+
+    // Add a span for `run`, one of the runs of the command `name`.
+    pub fn record(
+        &self,
+        name: &str,
+        command: &Command,
+        run: &str,
+        started: SystemTime,
+        exit_code: i32,
+    ) {
+        let span = json!({
+            "traceId": self.trace_id,
+            "spanId": random_id(8),
+            "parentSpanId": self.span_id,
+            "name": run,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(started),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": [
+                attribute("cargo_cmd.package", json!(self.package)),
+                attribute("cargo_cmd.command", json!(name)),
+                attribute("cargo_cmd.phase", json!(self.phase(name, command))),
+                attribute("process.exit.code", json!(exit_code)),
+            ],
+            "status": status(exit_code),
+        });
+        self.spans.lock().unwrap().push(span);
+    }
+
+    pub fn export(&self, exit_code: i32) -> Result<(), String> {
+        let mut spans = vec![json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": format!("cargo cmd {}", self.command),
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.started),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": [
+                attribute("cargo_cmd.package", json!(self.package)),
+                attribute("cargo_cmd.command", json!(self.command)),
+                attribute("process.exit.code", json!(exit_code)),
+            ],
+            "status": status(exit_code),
+        })];
+        spans.append(&mut self.spans.lock().unwrap());
+        let trace = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        attribute("service.name", json!("cargo-cmd")),
+                        attribute("service.version", json!(VERSION)),
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "cargo-cmd", "version": VERSION },
+                    "spans": spans,
+                }],
+            }],
+        });
+
+        if self.destination.starts_with("http://") || self.destination.starts_with("https://") {
+            ureq::post(&self.destination)
+                .set("Content-Type", "application/json")
+                .send_string(&trace.to_string())
+                .map(|_| ())
+                .map_err(|e| format!("Could not send the trace: {}", e))
+        } else {
+            fs::write(&self.destination, format!("{}\n", trace)).or(Err(format!(
+                "Could not write the trace to {}",
+                self.destination
+            )))
+        }
+    }
+}
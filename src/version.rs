@@ -0,0 +1,23 @@
+use std::cmp::Ordering;
+
+// The numeric parts of a version like `1.2.3`, `v24.0.7` or `0.3.1-beta.1`.
+// Anything after the numbers is ignored.
+pub fn parse(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .take(3)
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+// Compare versions, treating missing parts as 0 so `24` and `24.0.0` are
+// the same.
+pub fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    let part = |version: &[u64], i: usize| version.get(i).cloned().unwrap_or(0);
+    (0..len)
+        .map(|i| part(a, i).cmp(&part(b, i)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
@@ -0,0 +1,150 @@
+// --verify-ci checks that the `cargo cmd` calls in the steps of a GitHub
+// Actions workflow run commands that exist, so renaming a command doesn't
+// break CI without anyone noticing until it runs.
+
+use command::{self, Command};
+use cycles;
+use serde_yaml::{self, Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use suggest;
+
+#[derive(Deserialize)]
+struct Workflow {
+    // A mapping rather than a map keeps the jobs in the order of the file.
+    #[serde(default)]
+    jobs: Mapping,
+}
+
+#[derive(Deserialize)]
+struct Job {
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    run: Option<String>,
+}
+
+// A call to a command that doesn't exist.
+struct Missing<'a> {
+    job: String,
+    step: usize,
+    line: Option<usize>,
+    call: &'a str,
+    name: &'a str,
+}
+
+// `call` with the word `name` after `cargo cmd` in it replaced with
+// `replacement`.
+fn replace(call: &str, name: &str, replacement: &str) -> String {
+    let start = call.find("cargo cmd").unwrap_or(0);
+    let is_word = |at: usize| {
+        let before = call[..at].chars().next_back();
+        let after = call[at + name.len()..].chars().next();
+        [before, after]
+            .iter()
+            .all(|c| c.is_none_or(|c| !c.is_alphanumeric() && !"-_:".contains(c)))
+    };
+    match call
+        .match_indices(name)
+        .map(|(at, _)| at)
+        .find(|&at| at > start && is_word(at))
+    {
+        Some(at) => format!("{}{}{}", &call[..at], replacement, &call[at + name.len()..]),
+        None => call.to_string(),
+    }
+}
+
+pub fn verify(
+    path: &Path,
+    commands: &HashMap<String, Command>,
+    services: &HashMap<String, BTreeMap<String, Command>>,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path).or(Err(format!("Could not read {}", path.display())))?;
+    let workflow: Workflow = serde_yaml::from_str(&text)
+        .map_err(|error| format!("Could not parse {}: {}", path.display(), error))?;
+    let jobs: Vec<(String, Job)> = workflow
+        .jobs
+        .into_iter()
+        .filter_map(|(name, job)| {
+            let name = match name {
+                Value::String(name) => name,
+                _ => return None,
+            };
+            Some((name, serde_yaml::from_value(job).ok()?))
+        })
+        .collect();
+
+    let lines: Vec<&str> = text.lines().collect();
+    // Where to look for the next call in the file, so the same call in two
+    // steps is found at both.
+    let mut searched = 0;
+    let mut calls = 0;
+    let mut missing = vec![];
+    for (job_name, job) in &jobs {
+        for (index, step) in job.steps.iter().enumerate() {
+            let run = match step.run {
+                Some(ref run) => run,
+                None => continue,
+            };
+            for call in run.lines().map(str::trim) {
+                let names = cycles::calls(call);
+                if names.is_empty() {
+                    continue;
+                }
+                let line = lines
+                    .iter()
+                    .skip(searched)
+                    .position(|line| line.contains(call))
+                    .map(|offset| searched + offset);
+                if let Some(line) = line {
+                    searched = line + 1;
+                }
+                // Names from expressions like `${{ matrix.command }}` can't
+                // be checked.
+                for name in names.into_iter().filter(|name| !name.starts_with('$')) {
+                    calls += 1;
+                    if command::steps(commands, name).is_empty() && !services.contains_key(name) {
+                        missing.push(Missing {
+                            job: job_name.clone(),
+                            step: index + 1,
+                            line: line.map(|line| line + 1),
+                            call,
+                            name,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        println!(
+            "All {} `cargo cmd` calls in {} run commands that exist",
+            calls,
+            path.display()
+        );
+        return Ok(());
+    }
+    println!("--- {}\n+++ Cargo.toml", path.display());
+    for missing in &missing {
+        let line = missing
+            .line
+            .map(|line| format!(", line {}", line))
+            .unwrap_or_default();
+        println!("@@ jobs.{}, step {}{} @@", missing.job, missing.step, line);
+        println!("-{}", missing.call);
+        match suggest::similar(commands, missing.name).first() {
+            Some(similar) => println!("+{}", replace(missing.call, missing.name, similar)),
+            None => println!("+# \"{}\" is not defined", missing.name),
+        }
+    }
+    Err(format!(
+        "{} of the `cargo cmd` calls in {} run commands that don't exist",
+        missing.len(),
+        path.display()
+    ))
+}
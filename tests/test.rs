@@ -1,8 +1,28 @@
 extern crate assert_cli;
 
+fn fixture(name: &str) -> assert_cli::Assert {
+    in_dir(&format!("tests/fixtures/{}", name))
+}
+
+fn in_dir(dir: &str) -> assert_cli::Assert {
+    assert_cli::Assert::command(&[env!("CARGO_BIN_EXE_cargo-cmd")])
+        .current_dir(dir)
+        .with_env(inherit())
+}
+
+fn main_binary() -> assert_cli::Assert {
+    assert_cli::Assert::main_binary().with_env(inherit())
+}
+
+// The environment of the tests, without the `CI` that CI services set, so
+// they run the same everywhere.
+fn inherit() -> assert_cli::Environment {
+    assert_cli::Environment::inherit().insert("CI", "")
+}
+
 #[test]
 fn it_shows_help_for_no_args() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .fails()
         .and()
         .stderr()
@@ -12,7 +32,7 @@ fn it_shows_help_for_no_args() {
 
 #[test]
 fn it_errors_if_cmd_not_found() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "notfound"])
         .fails()
         .and()
@@ -21,9 +41,44 @@ fn it_errors_if_cmd_not_found() {
         .unwrap();
 }
 
+#[test]
+fn it_exits_with_its_own_codes_for_its_errors() {
+    main_binary()
+        .with_args(&["cmd", "notfound"])
+        .fails_with(65)
+        .unwrap();
+
+    let dir = std::path::Path::new("target/tests/broken-manifest");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join("Cargo.toml"), "[package\nname = \"broken\"\n").unwrap();
+    in_dir("target/tests/broken-manifest")
+        .with_args(&["cmd", "build"])
+        .fails_with(64)
+        .unwrap();
+
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"broken\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = { cmd = \"echo build\", nice = \"ten\" }\n\
+         other = \"echo other\"\n",
+    )
+    .unwrap();
+    in_dir("target/tests/broken-manifest")
+        .with_args(&["cmd", "other"])
+        .fails_with(64)
+        .and()
+        .stderr()
+        .is(
+            "error: Invalid [package.metadata.commands] in Cargo.toml: command \"build\": \
+             invalid type: string \"ten\", expected i32 for key `nice`",
+        )
+        .unwrap();
+}
+
 #[test]
 fn it_succeeds_when_command_is_found() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "pass"])
         .succeeds()
         .and()
@@ -34,7 +89,7 @@ fn it_succeeds_when_command_is_found() {
 
 #[test]
 fn it_returns_the_exit_code_of_the_command_when_it_fails() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "fail"])
         .fails_with(42)
         .and()
@@ -45,7 +100,7 @@ fn it_returns_the_exit_code_of_the_command_when_it_fails() {
 
 #[test]
 fn it_passes_extra_arguments_to_the_command() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "echo", "hello planet"])
         .succeeds()
         .and()
@@ -54,9 +109,21 @@ fn it_passes_extra_arguments_to_the_command() {
         .unwrap();
 }
 
+#[cfg(windows)]
+#[test]
+fn it_quotes_extra_arguments_for_cmd_exe() {
+    main_binary()
+        .with_args(&["cmd", "echo", "C:\\Program Files\\app", "a & b"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("\"C:\\Program Files\\app\" \"a & b\"")
+        .unwrap();
+}
+
 #[test]
 fn it_runs_the_pre_command() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "chain"])
         .succeeds()
         .and()
@@ -67,7 +134,7 @@ fn it_runs_the_pre_command() {
 
 #[test]
 fn it_runs_the_post_command() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "chain"])
         .succeeds()
         .and()
@@ -78,7 +145,7 @@ fn it_runs_the_post_command() {
 
 #[test]
 fn it_labels_the_command_if_running_multiple() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "chain"])
         .succeeds()
         .and()
@@ -89,7 +156,7 @@ fn it_labels_the_command_if_running_multiple() {
 
 #[test]
 fn it_stops_the_chain_if_a_command_fails() {
-    assert_cli::Assert::main_binary()
+    main_binary()
         .with_args(&["cmd", "failchain"])
         .fails()
         .and()
@@ -97,3 +164,2100 @@ fn it_stops_the_chain_if_a_command_fails() {
         .doesnt_contain("[chain]")
         .unwrap();
 }
+
+#[test]
+fn it_runs_builtin_file_commands_inside_command_strings() {
+    main_binary()
+        .with_args(&["cmd", "builtins"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> cargo-cmd:mkdir -p target/builtins/a")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_builtin_file_commands_directly() {
+    main_binary()
+        .with_args(&["cmd", "cargo-cmd:rm", "--", "-f", "target/does-not-exist"])
+        .succeeds()
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "cargo-cmd:rm", "--", "-f"])
+        .succeeds()
+        .and()
+        .stderr()
+        .is("")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_builtin_file_command_fails() {
+    main_binary()
+        .with_args(&["cmd", "cargo-cmd:rm", "target/does-not-exist"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("cargo-cmd:rm: target/does-not-exist")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_tasks_from_makefile_toml() {
+    fixture("cargo-make")
+        .with_args(&["cmd", "hello"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo hello from cargo-make")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_task_dependencies_from_makefile_toml() {
+    fixture("cargo-make")
+        .with_args(&["cmd", "flow"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo hello from cargo-make && echo")
+        .unwrap();
+    // Dependencies shared by several tasks run once.
+    fixture("cargo-make")
+        .with_args(&["cmd", "diamond"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo hello from cargo-make && echo done && echo diamond \n")
+        .unwrap();
+    fixture("cargo-make")
+        .with_args(&["cmd", "multi-line"])
+        .fails()
+        .and()
+        .stdout()
+        .doesnt_contain("\nfirst")
+        .and()
+        .stdout()
+        .doesnt_contain("\nsecond")
+        .unwrap();
+}
+
+#[test]
+fn it_prefers_cargo_toml_commands_over_makefile_toml_tasks() {
+    fixture("cargo-make")
+        .with_args(&["cmd", "overridden"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo from Cargo.toml")
+        .unwrap();
+}
+
+#[test]
+fn it_quotes_the_args_of_makefile_toml_tasks() {
+    fixture("cargo-make")
+        .with_args(&["cmd", "quoted"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("a  b c;d\n")
+        .unwrap();
+}
+
+#[test]
+fn it_leaves_out_makefile_toml_tasks_it_cannot_map() {
+    for task in &["windows-only", "from-file", "after-file", "with-env"] {
+        fixture("cargo-make")
+            .with_args(&["cmd", task])
+            .fails()
+            .and()
+            .stderr()
+            .contains(format!("Command \"{}\" not found", task).as_str())
+            .unwrap();
+    }
+}
+
+#[test]
+fn it_substitutes_named_params() {
+    main_binary()
+        .with_args(&["cmd", "deploy", "env=prod", "region=us"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo deploying prod to us")
+        .unwrap();
+}
+
+#[test]
+fn it_uses_param_defaults_and_exposes_params_as_env_vars() {
+    main_binary()
+        .with_args(&["cmd", "deploy", "env=prod"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("deploying prod to eu\nprod")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_a_required_param_is_missing() {
+    main_binary()
+        .with_args(&["cmd", "deploy"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Missing required parameter \"env\" for command \"deploy\"")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_a_param_is_not_one_of_its_choices() {
+    main_binary()
+        .with_args(&["cmd", "deploy", "env=qa"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Invalid value \"qa\" for parameter \"env\", expected one of: prod, staging")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_from_included_files() {
+    fixture("include")
+        .with_args(&["cmd", "db-up"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo starting db")
+        .unwrap();
+}
+
+#[test]
+fn it_prefers_cargo_toml_commands_over_included_ones() {
+    fixture("include")
+        .with_args(&["cmd", "release"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo release from Cargo.toml")
+        .unwrap();
+}
+
+#[test]
+fn it_warns_if_two_included_files_define_the_same_command() {
+    fixture("include-conflict")
+        .with_args(&["cmd", "dup"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo b")
+        .and()
+        .stderr()
+        .contains("warning: Command \"dup\" is defined in both")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_two_included_files_define_the_same_command_in_strict_mode() {
+    fixture("include-conflict")
+        .with_args(&["cmd", "--strict", "dup"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Command \"dup\" is defined in both")
+        .unwrap();
+}
+
+#[test]
+fn it_only_includes_files_for_the_current_platform() {
+    fixture("include-os")
+        .with_args(&["cmd", "platform"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains(if cfg!(windows) {
+            "> echo windows"
+        } else {
+            "> echo unix"
+        })
+        .unwrap();
+}
+
+#[test]
+fn it_includes_files_for_all_platforms() {
+    fixture("include-os")
+        .with_args(&["cmd", "common"])
+        .succeeds()
+        .unwrap();
+}
+
+#[test]
+fn it_loads_the_commands_env_file() {
+    main_binary()
+        .with_args(&["cmd", "env-file"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Hello from the env file, planet")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_from_another_directory_with_chdir() {
+    main_binary()
+        .with_args(&["cmd", "--chdir", "tests/fixtures/include", "db-up"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> echo starting db")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_the_chdir_directory_does_not_exist() {
+    main_binary()
+        .with_args(&["cmd", "-C", "tests/fixtures/nope", "pass"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Could not change to directory tests/fixtures/nope")
+        .unwrap();
+}
+
+#[test]
+fn it_exposes_git_information_to_commands() {
+    main_binary()
+        .with_args(&["cmd", "git-info"])
+        .succeeds()
+        .and()
+        .stdout()
+        .doesnt_contain("sha= dirty=")
+        .unwrap();
+}
+
+#[test]
+fn it_stops_the_run_when_the_max_time_is_exceeded() {
+    main_binary()
+        .with_args(&["cmd", "--max-time", "500ms", "slow"])
+        .fails_with(124)
+        .and()
+        .stderr()
+        .contains("Skipped: postslow")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--max-time", "99999999999999999999999h", "pass"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Invalid duration \"99999999999999999999999h\", it's too long")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_the_command_with_the_given_niceness() {
+    main_binary()
+        .with_args(&["cmd", "nice"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("10")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_the_command_with_resource_limits() {
+    main_binary()
+        .with_args(&["cmd", "limits"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("524288\n600")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "limits-too-big"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Invalid size \"99999999999T\", it's too big")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_the_command_once_wait_for_succeeds() {
+    main_binary()
+        .with_args(&["cmd", "ready"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Waiting for `exit 0`\n> echo ready")
+        .unwrap();
+}
+
+#[test]
+fn it_errors_if_wait_for_times_out() {
+    main_binary()
+        .with_args(&["cmd", "never-ready"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("`exit 1` did not succeed within 300ms")
+        .unwrap();
+}
+
+#[test]
+fn it_reads_stdin_from_a_file() {
+    main_binary()
+        .with_args(&["cmd", "stdin"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("from a file")
+        .unwrap();
+}
+
+#[test]
+fn it_appends_output_to_a_file() {
+    std::fs::write("target/log.txt", "before\n").unwrap();
+    main_binary().with_args(&["cmd", "log"]).succeeds().unwrap();
+    assert_eq!(
+        std::fs::read_to_string("target/log.txt").unwrap(),
+        "before\nout\nerr\n"
+    );
+}
+
+#[test]
+fn it_only_passes_allowed_env_vars() {
+    main_binary()
+        .with_args(&["cmd", "hermetic"])
+        .with_env(inherit().insert("CARGO_CMD_TEST_SHELL", "sh"))
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("home= shell=sh")
+        .unwrap();
+}
+
+#[test]
+fn it_picks_the_command_for_the_target() {
+    main_binary()
+        .with_args(&["cmd", "package-app"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("generic")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--target", "aarch64-apple-darwin", "package-app"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("macos aarch64-apple-darwin")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_a_command_for_each_matrix_combination() {
+    main_binary()
+        .with_args(&["cmd", "matrix"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[matrix feature=no-tls toolchain=stable]\n> echo no-tls on $CARGO_CMD_MATRIX_TOOLCHAIN \nno-tls on stable")
+        .and()
+        .stdout()
+        .contains("[matrix feature=tls toolchain=stable]")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_a_matrix_in_parallel() {
+    let started = std::time::Instant::now();
+    main_binary()
+        .with_args(&["cmd", "matrix-parallel"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("run 1")
+        .and()
+        .stdout()
+        .contains("run 3")
+        .unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+}
+
+#[test]
+fn it_never_runs_commands_with_the_same_mutex_at_once() {
+    let started = std::time::Instant::now();
+    main_binary()
+        .with_args(&["cmd", "matrix-mutex"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Waiting for mutex \"sleepy\"")
+        .unwrap();
+    assert!(started.elapsed() >= std::time::Duration::from_secs(2));
+}
+
+#[test]
+fn it_runs_a_parallel_matrix_sequentially_when_forced() {
+    main_binary()
+        .with_args(&["cmd", "--force-sequential", "matrix-parallel"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("run 1\n\n[matrix-parallel n=2]")
+        .and()
+        .stdout()
+        .contains("run 2\n\n[matrix-parallel n=3]")
+        .unwrap();
+}
+
+#[test]
+fn it_leaves_escaped_placeholders_alone() {
+    main_binary()
+        .with_args(&["cmd", "escaped"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("shell param\n")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "verbatim"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("\nshell\n")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_calls_functions_in_placeholders() {
+    let run = |program: &str, args: &[&str]| {
+        let output = std::process::Command::new(program)
+            .args(args)
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-cmd"))
+        .args(["cmd", "functions"])
+        .env("CARGO_CMD_TEST_VALUE", "value")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let words: Vec<&str> = stdout.lines().last().unwrap().split(' ').collect();
+    assert_eq!(words[0], "value");
+    assert_eq!(words[1], run("date", &["-u", "+%Y-%m-%d"]));
+    assert_eq!(words[2], run("git", &["rev-parse", "--short", "HEAD"]));
+    assert_eq!(words[3].len(), 36);
+    assert_eq!(&words[3][14..15], "4");
+}
+
+#[test]
+fn it_runs_commands_for_the_features_selected() {
+    main_binary()
+        .with_args(&["cmd", "features"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("checking --features postgres,tls\n")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--features", "tls", "features"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Skipping `prefeatures`, it needs the features postgres")
+        .and()
+        .stdout()
+        .doesnt_contain("checking")
+        .and()
+        .stdout()
+        .contains("done")
+        .unwrap();
+}
+
+#[test]
+fn it_leaves_matrix_keys_named_like_placeholders_to_the_matrix() {
+    main_binary()
+        .with_args(&[
+            "cmd",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "matrix-features",
+        ])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("features=tls target=wasm\n")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_with_their_toolchain() {
+    main_binary()
+        .with_args(&["cmd", "toolchain"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("\nstable")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "toolchain-missing"])
+        .fails()
+        .and()
+        .stderr()
+        .contains(
+            "    toolchain nightly-2000-01-01 (not installed, \
+             install it with `rustup toolchain install nightly-2000-01-01`)",
+        )
+        .unwrap();
+}
+
+#[test]
+fn it_reports_all_missing_tools_before_running() {
+    main_binary()
+        .with_args(&["cmd", "requires"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Missing required tools:\n    cargo < 1.0 (found 1.")
+        .and()
+        .stderr()
+        .contains("    not-a-real-tool (not found)")
+        .and()
+        .stdout()
+        .doesnt_contain("ok")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_prepends_directories_to_path() {
+    main_binary()
+        .with_args(&["cmd", "path-prepend"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("hello from scripts")
+        .unwrap();
+}
+
+#[test]
+fn it_reports_resource_usage() {
+    main_binary()
+        .with_args(&["cmd", "--report-usage", "pass"])
+        .succeeds()
+        .and()
+        .stderr()
+        .contains("Took ")
+        .and()
+        .stderr()
+        .contains("peak memory)")
+        .unwrap();
+}
+
+#[test]
+fn it_maps_exit_codes_to_success() {
+    main_binary()
+        .with_args(&["cmd", "benign"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[postbenign]\n> echo benign")
+        .unwrap();
+}
+
+#[test]
+fn it_resumes_and_skips_parts_of_the_chain() {
+    fixture("steps")
+        .with_args(&["cmd", "--from", "pretest.2", "test"])
+        .succeeds()
+        .and()
+        .stdout()
+        .doesnt_contain("starting the database")
+        .and()
+        .stdout()
+        .contains("migrating")
+        .unwrap();
+    fixture("steps")
+        .with_args(&["cmd", "--skip", "pretest", "test"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("> echo testing \ntesting")
+        .unwrap();
+    fixture("steps")
+        .with_args(&["cmd", "--skip", "seed", "test"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("\"seed\" doesn't run as part of `cargo cmd test`")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_retries_the_last_run_from_the_command_that_failed() {
+    let dir = std::path::Path::new("target/tests/retry");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"retry\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\npretest = \"echo prepared\"\n\
+         test = \"test -f fixed\"\nposttest = \"echo done\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/retry")
+        .with_args(&["cmd", "test"])
+        .fails()
+        .unwrap();
+    std::fs::write(dir.join("fixed"), "").unwrap();
+    in_dir("target/tests/retry")
+        .with_args(&["cmd", "--retry-failed"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Retrying `cargo cmd test` from test")
+        .and()
+        .stdout()
+        .doesnt_contain("prepared")
+        .and()
+        .stdout()
+        .contains("done")
+        .unwrap();
+    in_dir("target/tests/retry")
+        .with_args(&["cmd", "--retry-failed"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("The last run succeeded, there's nothing to retry")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_commands_in_the_background() {
+    let dir = std::path::Path::new("target/tests/jobs");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"jobs\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\n\
+         prestart = { cmd = \"sleep 30\", background = true }\n\
+         start = \"echo started\"\n",
+    )
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    in_dir("target/tests/jobs")
+        .with_args(&["cmd", "start"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Started `prestart` in the background (pid ")
+        .and()
+        .stdout()
+        .contains("started")
+        .unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    in_dir("target/tests/jobs")
+        .with_args(&["cmd", "--jobs-list"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("prestart  (pid ")
+        .unwrap();
+    in_dir("target/tests/jobs")
+        .with_args(&["cmd", "--stop", "prestart"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Stopped `prestart`")
+        .unwrap();
+    in_dir("target/tests/jobs")
+        .with_args(&["cmd", "--jobs-list"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("No jobs are running")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_services_together() {
+    let dir = std::path::Path::new("target/tests/services");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"services\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = \"cargo build\"\n\n\
+         [package.metadata.cargo-cmd]\nenv_deny = [\"HOME\"]\n\n\
+         [package.metadata.cargo-cmd.services.dev]\n\
+         web = \"echo web is up, home=$HOME\"\n\
+         worker = { cmd = \"test -f crashed || { touch crashed; exit 3; }\", restart = \"on-failure\" }\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/services")
+        .with_args(&["cmd", "dev"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("web    | web is up, home=\n")
+        .and()
+        .stdout()
+        .contains("worker | exited with 3, restarting")
+        .and()
+        .stdout()
+        .contains("worker | exited with 0")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_starts_services_once_their_dependencies_are_ready() {
+    let dir = std::path::Path::new("target/tests/services-ready");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"services-ready\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = \"cargo build\"\n\n\
+         [package.metadata.cargo-cmd.services.dev]\n\
+         web = { cmd = \"sleep 0.5; echo listening on 8080; sleep 1\", wait_for_log = \"listening on\" }\n\
+         worker = { cmd = \"echo started\", depends_on = [\"web\"] }\n\n\
+         [package.metadata.cargo-cmd.services.slow]\n\
+         web = { cmd = \"trap '' TERM; sleep 30\", wait_for_log = \"listening on\", startup_timeout = \"500ms\" }\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/services-ready")
+        .with_args(&["cmd", "dev"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("web    | listening on 8080\nweb    | ready\nworker | started")
+        .unwrap();
+    // The service ignores SIGTERM and is killed after the grace period.
+    let started = std::time::Instant::now();
+    in_dir("target/tests/services-ready")
+        .with_args(&["cmd", "slow"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Service \"web\" was not ready within 500ms")
+        .unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[cfg(unix)]
+#[test]
+fn it_gives_services_free_ports() {
+    let dir = std::path::Path::new("target/tests/services-ports");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"services-ports\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = \"cargo build\"\n\n\
+         [package.metadata.cargo-cmd.services.dev]\n\
+         api = \"test ${port(auto)} -gt 0 && echo listening; echo params: $(env | grep -c CARGO_CMD_PARAM_)\"\n\
+         web = \"test ${port(auto)} != $CARGO_CMD_PORT_API && echo talking to $CARGO_CMD_PORT_API\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/services-ports")
+        .with_args(&["cmd", "dev"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("api | listening")
+        .and()
+        .stdout()
+        .contains("api | params: 0")
+        .and()
+        .stdout()
+        .contains("web | talking to ")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_rotates_service_logs() {
+    let dir = std::path::Path::new("target/tests/services-logs");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"services-logs\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = \"cargo build\"\n\n\
+         [package.metadata.cargo-cmd.services.dev]\n\
+         web = { cmd = \"for i in 1 2 3 4 5 6 7; do echo line $i; done\", \
+         stdout_file = \"web.log\", log_rotate = \"60B\", log_keep = 2 }\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/services-logs")
+        .with_args(&["cmd", "dev"])
+        .succeeds()
+        .and()
+        .stdout()
+        .doesnt_contain("web | line")
+        .unwrap();
+    // Each line starts with the time it was written.
+    let read = |name: &str| {
+        let log = std::fs::read_to_string(dir.join(name)).unwrap();
+        log.lines()
+            .map(|line| format!("{}\n", &line[21..]))
+            .collect::<String>()
+    };
+    assert_eq!(read("web.log"), "line 7\n");
+    assert_eq!(read("web.log.1"), "line 5\nline 6\n");
+    assert_eq!(read("web.log.2"), "line 3\nline 4\n");
+    assert!(!dir.join("web.log.3").exists());
+    let kept = std::fs::read_to_string(dir.join("web.log.2")).unwrap();
+    let recent = kept.lines().nth(1).unwrap().to_string();
+    std::fs::write(
+        dir.join("web.log.2"),
+        format!("2000-01-01T00:00:00Z line 3\n{}\n", recent),
+    )
+    .unwrap();
+    in_dir("target/tests/services-logs")
+        .with_args(&["cmd", "--logs", "dev.web", "--since", "1h"])
+        .succeeds()
+        .and()
+        .stdout()
+        .doesnt_contain("line 3")
+        .and()
+        .stdout()
+        .contains(" line 4\n")
+        .and()
+        .stdout()
+        .contains(" line 7")
+        .unwrap();
+    in_dir("target/tests/services-logs")
+        .with_args(&["cmd", "--logs", "worker"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("No logs for \"worker\", it isn't a background command or a service")
+        .unwrap();
+}
+
+#[test]
+fn it_exposes_package_metadata() {
+    fixture("metadata")
+        .with_args(&["cmd", "docker"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("example/metadata:8080 latest 1.0")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_keeps_package_metadata_out_of_the_env() {
+    fixture("metadata")
+        .with_args(&["cmd", "env"])
+        .succeeds()
+        .and()
+        .stdout()
+        .doesnt_contain("CARGO_CMD_PARAM_METADATA")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_only_the_selected_phases() {
+    main_binary()
+        .with_args(&["cmd", "--only-pre", "--only-post", "chain"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[prechain]\n> echo 1")
+        .and()
+        .stdout()
+        .contains("[postchain]\n> echo 3")
+        .and()
+        .stdout()
+        .doesnt_contain("echo 2")
+        .unwrap();
+}
+
+#[test]
+fn it_propagates_the_env_of_a_hook() {
+    main_binary()
+        .with_args(&["cmd", "propagate"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("version 1.2.3")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_simple_commands_without_a_shell() {
+    main_binary()
+        .with_args(&["cmd", "not-found"])
+        .fails_with(66)
+        .and()
+        .stderr()
+        .contains("error: Could not run \"./target/cargo-cmd-missing-program\"")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "not-found-shell"])
+        .fails_with(127)
+        .unwrap();
+    // Programs that aren't on the PATH are left to the shell.
+    main_binary()
+        .with_args(&["cmd", "--exec", "cargo-cmd-missing-program --flag"])
+        .fails_with(127)
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--exec", "cd target"])
+        .succeeds()
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--exec", "command -v sh"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("/sh")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_runs_scripts() {
+    main_binary()
+        .with_args(&["cmd", "script"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains(
+            "> tests/fixtures/script/release.rhai\n> echo building cargo-cmd\n\
+             building cargo-cmd\nrun exited with 0",
+        )
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "script", "3"])
+        .fails_with(3)
+        .and()
+        .stdout()
+        .contains("> exit 3")
+        .unwrap();
+    let started = std::time::Instant::now();
+    main_binary()
+        .with_args(&["cmd", "--max-time", "500ms", "script-slow"])
+        .fails_with(124)
+        .and()
+        .stdout()
+        .doesnt_contain("still running")
+        .unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[test]
+fn it_runs_in_ci_mode() {
+    main_binary()
+        .with_args(&["cmd", "--ci", "matrix-parallel"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[matrix-parallel n=1]\nrun 1\n\n[matrix-parallel n=2]\nrun 2\n")
+        .and()
+        .stderr()
+        .contains(r#"{"schema":1,"#)
+        .and()
+        .stderr()
+        .contains(r#""command":"matrix-parallel""#)
+        .and()
+        .stderr()
+        .contains(r#""status":0,"commands":[{"name":"matrix-parallel n="#)
+        .and()
+        .stderr()
+        .contains(r#""peak_memory":"#)
+        .and()
+        .stdout()
+        .doesnt_contain("schema")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--exec", "echo no_color=$NO_COLOR"])
+        .with_env(inherit().insert("CI", "true"))
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("no_color=1")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_silences_quiet_commands_unless_they_fail() {
+    main_binary()
+        .with_args(&["cmd", "noisy"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("[noisy]\n> echo main \nmain")
+        .and()
+        .stderr()
+        .contains("Running [\"echo\", \"main\"]\nTook ")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "noisy-fail"])
+        .fails_with(3)
+        .and()
+        .stdout()
+        .is("compile error")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_by_tag() {
+    main_binary()
+        .with_args(&["cmd", "--tag", "ci"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("> cargo cmd lint\n> echo linting \nlinting\n> cargo cmd unit\n")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "--tag", "nope"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("error: No commands are tagged \"nope\"")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_passes_the_target_and_profile_on() {
+    main_binary()
+        .with_args(&[
+            "cmd",
+            "--target",
+            "wasm32-unknown-unknown",
+            "--profile",
+            "release",
+            "--exec",
+            "echo ${target}/${profile} $CARGO_CMD_TARGET/$CARGO_CMD_PROFILE",
+        ])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("\nwasm32-unknown-unknown/release wasm32-unknown-unknown/release\n")
+        .unwrap();
+}
+
+#[test]
+fn it_follows_cargos_terminal_settings() {
+    main_binary()
+        .with_args(&["cmd", "chain"])
+        .with_env(inherit().insert("CARGO_TERM_QUIET", "true"))
+        .succeeds()
+        .and()
+        .stdout()
+        .is("1\n2\n3")
+        .unwrap();
+    main_binary()
+        .with_args(&["cmd", "nope"])
+        .with_env(inherit().insert("CARGO_TERM_COLOR", "always"))
+        .fails()
+        .and()
+        .stderr()
+        .is("\x1b[1;31merror:\x1b[0m Command \"nope\" not found in Cargo.toml")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_an_ad_hoc_command_with_hooks() {
+    main_binary()
+        .with_args(&["cmd", "--exec", "echo ad hoc", "--as", "chain"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[prechain]\n> echo 1 \n1\n\n[chain]\n> echo ad hoc \nad hoc\n\n[postchain]")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_wildcard_hooks_around_the_command_hooks() {
+    fixture("wildcard-hooks")
+        .with_args(&["cmd", "build"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[pre*]\n> echo starting")
+        .and()
+        .stdout()
+        .contains("[postbuild]\n> echo after build \nafter build\n\n[post*]")
+        .unwrap();
+}
+
+#[test]
+fn it_stops_before_the_wildcard_post_hook_if_the_command_fails() {
+    fixture("wildcard-hooks")
+        .with_args(&["cmd", "fail"])
+        .fails_with(3)
+        .and()
+        .stdout()
+        .doesnt_contain("[post*]")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_setup_first_and_teardown_last() {
+    fixture("session")
+        .with_args(&["cmd", "test"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[setup]\n> echo compose up")
+        .and()
+        .stdout()
+        .contains("[posttest]\n> echo cleaning \ncleaning\n\n[teardown]")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_teardown_even_if_a_command_fails() {
+    fixture("session")
+        .with_args(&["cmd", "fail"])
+        .fails_with(5)
+        .and()
+        .stdout()
+        .contains("[teardown]\n> echo compose down")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_interactively() {
+    main_binary()
+        .with_args(&["cmd", "-i"])
+        .stdin("pass\nfail\nexit\n")
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("cargo cmd> > exit 0 \ncargo cmd> > exit 42")
+        .and()
+        .stderr()
+        .contains("Exited with code 42")
+        .unwrap();
+}
+
+#[test]
+fn it_exports_a_trace_of_the_run() {
+    fixture("session")
+        .with_args(&["cmd", "--trace", "../../../target/trace.json", "fail"])
+        .fails_with(5)
+        .unwrap();
+    let trace = std::fs::read_to_string("target/trace.json").unwrap();
+    assert!(trace.contains(r#""name":"cargo cmd fail""#));
+    assert!(
+        trace.contains(r#"{"key":"cargo_cmd.package","value":{"stringValue":"session-fixture"}}"#)
+    );
+    assert!(trace.contains(r#"{"key":"cargo_cmd.phase","value":{"stringValue":"teardown"}}"#));
+    assert!(trace.contains(r#"{"key":"process.exit.code","value":{"intValue":"5"}}"#));
+}
+
+#[test]
+fn it_shows_the_history_of_runs() {
+    fixture("session")
+        .with_args(&["cmd", "test", "history"])
+        .succeeds()
+        .unwrap();
+    fixture("session")
+        .with_args(&["cmd", "--history"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("exit 0    test history\n")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_compares_the_durations_of_runs() {
+    let dir = std::path::Path::new("target/tests/compare");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"compare\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nprebench = \"echo warming up\"\nbench = \"sleep 0.1\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/compare")
+        .with_args(&["cmd", "bench"])
+        .succeeds()
+        .unwrap();
+    let journal = std::fs::read_to_string(dir.join("target/cargo-cmd/journal.jsonl")).unwrap();
+    assert!(journal.contains(r#""name":"bench","duration":"#));
+    assert!(journal.contains(r#""peak_memory":"#));
+    in_dir("target/tests/compare")
+        .with_args(&["cmd", "--compare"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Only one run of `bench` recorded, there's nothing to compare it to")
+        .unwrap();
+    in_dir("target/tests/compare")
+        .with_args(&["cmd", "bench"])
+        .succeeds()
+        .unwrap();
+    in_dir("target/tests/compare")
+        .with_args(&["cmd", "bench", "--compare", "HEAD"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("before     after")
+        .and()
+        .stdout()
+        .contains("  prebench")
+        .and()
+        .stdout()
+        .contains("  bench ")
+        .and()
+        .stdout()
+        .contains("  total ")
+        .unwrap();
+}
+
+#[test]
+fn it_cleans_up_target_cargo_cmd() {
+    let dir = std::path::Path::new("target/tests/clean");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join("target/cargo-cmd/jobs")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"clean\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nhello = \"echo hello\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("target/cargo-cmd/jobs/server.json"),
+        r#"{"name":"server","pid":0,"cmd":"exit 0","log":null}"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("target/cargo-cmd/jobs/server.log"), "").unwrap();
+
+    in_dir("target/tests/clean")
+        .with_args(&["cmd", "hello"])
+        .succeeds()
+        .unwrap();
+    in_dir("target/tests/clean")
+        .with_args(&["cmd", "--clean", "logs"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("Removed target/cargo-cmd/jobs/server.log")
+        .unwrap();
+    in_dir("target/tests/clean")
+        .with_args(&["cmd", "--clean"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Removed target/cargo-cmd/cache")
+        .and()
+        .stdout()
+        .contains("Removed target/cargo-cmd/jobs/server.json")
+        .and()
+        .stdout()
+        .contains("Removed target/cargo-cmd/journal.jsonl")
+        .unwrap();
+    assert!(!dir.join("target/cargo-cmd").exists());
+}
+
+#[test]
+fn it_sends_a_webhook_notification() {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![];
+        let mut buffer = [0; 4096];
+        while !String::from_utf8_lossy(&request).contains("\"status\"") {
+            let read = stream.read(&mut buffer).unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    let dir = std::path::Path::new("target/tests/notify");
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"notify\"\nversion = \"0.1.0\"\n\n\
+             [package.metadata.commands]\ndeploy = \"exit 3\"\n\n\
+             [package.metadata.cargo-cmd.notifications]\n\
+             webhook = \"http://127.0.0.1:{}/hook\"\ncommands = [\"deploy\"]\n",
+            port
+        ),
+    )
+    .unwrap();
+
+    in_dir("target/tests/notify")
+        .with_args(&["cmd", "deploy"])
+        .fails_with(3)
+        .unwrap();
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /hook "));
+    assert!(request.contains("`cargo cmd deploy` failed with exit code 3 in notify"));
+}
+
+#[test]
+fn it_exports_the_commands_as_markdown() {
+    let dir = std::path::Path::new("target/tests/export");
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"export\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\n\
+         lint = { cmd = \"cargo clippy | tee lint.txt\", description = \"Run clippy\" }\n\
+         prelint = \"cargo fmt --check\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/export")
+        .with_args(&["cmd", "--export", "markdown"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("Wrote COMMANDS.md")
+        .unwrap();
+    let markdown = std::fs::read_to_string(dir.join("COMMANDS.md")).unwrap();
+    assert!(markdown.ends_with(
+        "| `lint` | Run clippy | `cargo clippy \\| tee lint.txt` | `prelint`: `cargo fmt --check` |\n"
+    ));
+}
+
+#[test]
+fn it_exports_the_commands_as_vscode_tasks() {
+    let dir = std::path::Path::new("target/tests/export-vscode");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join(".vscode")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"export-vscode\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\n\
+         build = { cmd = \"cargo build\", description = \"Build it\" }\n\
+         prebuild = \"cargo fmt --check\"\n\
+         serve = \"python3 -m http.server\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join(".vscode/tasks.json"),
+        r#"{"version": "2.0.0", "tasks": [{"label": "mine", "type": "shell", "command": "make"},
+            {"label": "cargo cmd: gone", "type": "shell", "command": "cargo cmd gone"}]}"#,
+    )
+    .unwrap();
+
+    in_dir("target/tests/export-vscode")
+        .with_args(&["cmd", "--export", "vscode"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("Wrote .vscode/tasks.json")
+        .unwrap();
+    let tasks = std::fs::read_to_string(dir.join(".vscode/tasks.json")).unwrap();
+    assert!(tasks.contains("\"label\": \"mine\""));
+    assert!(!tasks.contains("cargo cmd: gone"));
+    assert!(!tasks.contains("cargo cmd: prebuild"));
+    assert!(tasks.contains(
+        "\"command\": \"cargo cmd build\",\n      \"detail\": \"Build it\",\n      \
+         \"group\": \"build\",\n      \"label\": \"cargo cmd: build\",\n      \
+         \"problemMatcher\": [\n        \"$rustc\"\n      ],"
+    ));
+    assert!(tasks.contains("\"label\": \"cargo cmd: serve\",\n      \"problemMatcher\": [],"));
+}
+
+#[test]
+fn it_exports_the_commands_as_idea_run_configurations() {
+    let dir = std::path::Path::new("target/tests/export-idea");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join(".idea/runConfigurations")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"export-idea\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\n\
+         \"ops:deploy\" = \"./deploy.sh\"\n\
+         prelint = \"cargo fmt --check\"\n\
+         lint = \"cargo clippy\"\n",
+    )
+    .unwrap();
+    let configurations = dir.join(".idea/runConfigurations");
+    std::fs::write(configurations.join("cargo_cmd_gone.xml"), "").unwrap();
+    std::fs::write(configurations.join("Mine.xml"), "").unwrap();
+
+    in_dir("target/tests/export-idea")
+        .with_args(&["cmd", "--export", "idea"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("Wrote 2 run configurations to .idea/runConfigurations")
+        .unwrap();
+    assert!(!configurations.join("cargo_cmd_gone.xml").exists());
+    assert!(configurations.join("Mine.xml").exists());
+    assert!(!configurations.join("cargo_cmd_prelint.xml").exists());
+    let deploy = std::fs::read_to_string(configurations.join("cargo_cmd_ops_deploy.xml")).unwrap();
+    assert!(deploy.contains(
+        "<configuration default=\"false\" name=\"cargo cmd: ops:deploy\" type=\"ShConfigurationType\">\n    \
+         <option name=\"SCRIPT_TEXT\" value=\"cargo cmd ops:deploy\" />"
+    ));
+}
+
+#[test]
+fn it_verifies_the_commands_a_workflow_runs() {
+    fixture("workflow")
+        .with_args(&["cmd", "--verify-ci", "ci.yml"])
+        .fails()
+        .and()
+        .stdout()
+        .is("--- ci.yml\n+++ Cargo.toml\n\
+             @@ jobs.check, step 3, line 11 @@\n\
+             -cargo cmd --max-time 10m tests\n\
+             +cargo cmd --max-time 10m test\n\
+             @@ jobs.matrix, step 2, line 19 @@\n\
+             -cargo cmd deploy\n\
+             +# \"deploy\" is not defined")
+        .and()
+        .stderr()
+        .contains("2 of the `cargo cmd` calls in ci.yml run commands that don't exist")
+        .unwrap();
+    fixture("workflow")
+        .with_args(&["cmd", "--verify-ci", "fixed.yml"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("All 2 `cargo cmd` calls in fixed.yml run commands that exist")
+        .unwrap();
+}
+
+#[test]
+fn it_explains_how_a_command_resolves() {
+    fixture("include")
+        .with_args(&["cmd", "--explain", "release"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains(
+            "1. release: echo release from Cargo.toml\n     \
+             from Cargo.toml:9, overriding commands/release.toml:1, as `package.metadata.commands.release`",
+        )
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_named_like_settings() {
+    let dir = std::path::Path::new("target/tests/settings-names");
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"settings-names\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\ninclude = \"echo including\"\n\
+         parallel = \"echo in parallel\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/settings-names")
+        .with_args(&["cmd", "include"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("including")
+        .and()
+        .stderr()
+        .is("")
+        .unwrap();
+    in_dir("target/tests/settings-names")
+        .with_args(&["cmd", "parallel"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("in parallel")
+        .unwrap();
+}
+
+#[test]
+fn it_falls_back_to_cargo_subcommands() {
+    let dir = std::path::Path::new("target/tests/fallback");
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"fallback\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.cargo-cmd]\nfallback_to_cargo = true\n\n\
+         [package.metadata.commands]\npreversion = \"echo before\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/fallback")
+        .with_args(&["cmd", "version"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("[preversion]\n> echo before \nbefore\n\n[version]\n> cargo version \ncargo ")
+        .unwrap();
+
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"fallback\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.cargo-cmd]\nfallback_to_cargo = \"yes\"\n\n\
+         [package.metadata.commands]\npreversion = \"echo before\"\n",
+    )
+    .unwrap();
+    in_dir("target/tests/fallback")
+        .with_args(&["cmd", "version"])
+        .fails_with(64)
+        .and()
+        .stderr()
+        .contains("Invalid [package.metadata.cargo-cmd] in Cargo.toml")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_commands_from_extra_manifests_in_their_directory() {
+    fixture("monorepo")
+        .with_args(&["cmd", "ops:where"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("monorepo/ops\n")
+        .unwrap();
+    fixture("monorepo")
+        .with_args(&["cmd", "--explain", "frontend:build"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("1. frontend:build: npm run build\n     from frontend/package.json")
+        .unwrap();
+}
+
+#[test]
+fn it_builds_before_commands_that_need_it() {
+    fixture("needs-build")
+        .with_args(&["cmd", "hello"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("> cargo build --profile release\n> target/release/needs-build \nHello from the build")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_passes_the_path_of_a_built_binary() {
+    fixture("needs-build")
+        .with_args(&["cmd", "bin-path"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("needs-build/target/debug/needs-build\nHello from the build")
+        .unwrap();
+}
+
+#[test]
+fn it_reports_commands_that_run_each_other_in_a_loop() {
+    fixture("loops")
+        .with_args(&["cmd", "deploy"])
+        .fails()
+        .and()
+        .stderr()
+        .contains(
+            "error: Commands run each other in a loop: \
+             deploy -> build -> check (from prebuild) -> deploy",
+        )
+        .and()
+        .stdout()
+        .is("")
+        .unwrap();
+}
+
+#[test]
+fn it_suggests_commands_that_were_meant() {
+    fixture("typos")
+        .with_args(&["cmd", "tests"])
+        .fails()
+        .and()
+        .stderr()
+        .is("error: Command \"tests\" not found in Cargo.toml\n    Did you mean \"test\"?")
+        .unwrap();
+    fixture("typos")
+        .with_args(&["cmd", "test"])
+        .fails()
+        .and()
+        .stderr()
+        .is("error: Command \"test\" not found in Cargo.toml\n    \
+             \"pretest\" is defined, but not \"test\" itself")
+        .unwrap();
+}
+
+#[test]
+fn it_runs_lists_of_hooks_in_order() {
+    fixture("steps")
+        .with_args(&["cmd", "test"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("starting the database\n\n[pretest.2]")
+        .and()
+        .stdout()
+        .contains("migrating\n\n[test]")
+        .unwrap();
+    fixture("steps")
+        .with_args(&["cmd", "--explain", "test"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("2. pretest.2: echo migrating\n     pre hook, from Cargo.toml:6")
+        .unwrap();
+}
+
+#[test]
+fn it_only_treats_the_commands_of_lists_as_steps() {
+    fixture("steps")
+        .with_args(&["cmd", "--explain", "v1.2"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("1. v1.2: echo releasing\n     from Cargo.toml:8,")
+        .unwrap();
+}
+
+#[test]
+fn it_lists_commands_by_namespace() {
+    fixture("monorepo")
+        .with_args(&["cmd", "--list"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("  build           (from Cargo.toml:9)\n\n\
+             frontend:\n  frontend:build  (from frontend/package.json:4)\n\n\
+             ops:\n  ops:where       Show where ops commands run (from ops/Commands.toml:1)")
+        .unwrap();
+}
+
+#[test]
+fn it_lists_commands_in_other_formats() {
+    fixture("monorepo")
+        .with_args(&["cmd", "--list", "--format", "plain"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("build\nfrontend:build\nops:where")
+        .unwrap();
+    fixture("steps")
+        .with_args(&["cmd", "--list", "--format", "json"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains(
+            "\"name\": \"test\",\n    \"description\": null,\n    \
+             \"source\": \"Cargo.toml:7\",\n    \"tags\": [],\n    \
+             \"hooks\": [\n      \"pretest\"\n    ]",
+        )
+        .unwrap();
+}
+
+#[test]
+fn it_searches_commands() {
+    fixture("monorepo")
+        .with_args(&["cmd", "--search", "BUILD"])
+        .succeeds()
+        .and()
+        .stdout()
+        .is("build: echo building the backend\n    from Cargo.toml:9\n\
+             frontend:build: npm run build\n    from frontend/package.json:4")
+        .unwrap();
+    fixture("monorepo")
+        .with_args(&["cmd", "--search", "docker"])
+        .fails_with(1)
+        .and()
+        .stderr()
+        .is("No commands match \"docker\"")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_starts_a_shell_with_the_commands_environment() {
+    main_binary()
+        .with_args(&["cmd", "--shell-env", "env-file"])
+        .stdin("echo $GREETING from $CARGO_CMD_SHELL_ENV")
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Hello from the env file from env-file")
+        .unwrap();
+}
+
+#[test]
+fn it_installs_and_removes_cargo_aliases() {
+    let dir = std::path::Path::new("target/tests/aliases");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join(".cargo")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"aliases\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nci = \"echo ci\"\ntest = \"cargo test\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join(".cargo/config.toml"),
+        "# keep me\n[alias]\nb = \"build\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/aliases")
+        .with_args(&["cmd", "--install-aliases"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Skipped `test`, cargo already has a `test` command")
+        .unwrap();
+    let config = std::fs::read_to_string(dir.join(".cargo/config.toml")).unwrap();
+    assert_eq!(
+        config,
+        "# keep me\n[alias]\nb = \"build\"\nci = \"cmd ci\"\n"
+    );
+
+    in_dir("target/tests/aliases")
+        .with_args(&["cmd", "--remove-aliases"])
+        .succeeds()
+        .unwrap();
+    let config = std::fs::read_to_string(dir.join(".cargo/config.toml")).unwrap();
+    assert_eq!(config, "# keep me\n[alias]\nb = \"build\"\n");
+}
+
+#[test]
+fn it_installs_and_removes_shell_aliases() {
+    let dir = std::path::Path::new("target/tests/shell-aliases");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join("home")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"shell-aliases\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\nbuild = \"echo build\"\n\
+         bench = \"echo bench\"\nrun-server = \"echo serving\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("home/.bashrc"), "# keep me\n").unwrap();
+    let env = inherit()
+        .insert("HOME", dir.join("home").canonicalize().unwrap())
+        .insert("SHELL", "/bin/bash");
+
+    in_dir("target/tests/shell-aliases")
+        .with_env(&env)
+        .with_args(&["cmd", "--install-shell-aliases"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("Skipped `build`, `ccb` is already `cargo cmd bench`")
+        .unwrap();
+    let rc = std::fs::read_to_string(dir.join("home/.bashrc")).unwrap();
+    assert_eq!(
+        rc,
+        "# keep me\n\n\
+         # >>> cargo-cmd aliases for shell-aliases >>>\n\
+         alias ccb='cargo cmd bench'\n\
+         alias ccrs='cargo cmd run-server'\n\
+         # <<< cargo-cmd aliases for shell-aliases <<<\n"
+    );
+
+    // Installing them again replaces the block as it was.
+    in_dir("target/tests/shell-aliases")
+        .with_env(&env)
+        .with_args(&["cmd", "--install-shell-aliases"])
+        .succeeds()
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("home/.bashrc")).unwrap(),
+        rc
+    );
+
+    in_dir("target/tests/shell-aliases")
+        .with_env(&env)
+        .with_args(&["cmd", "--remove-shell-aliases"])
+        .succeeds()
+        .unwrap();
+    let rc = std::fs::read_to_string(dir.join("home/.bashrc")).unwrap();
+    assert_eq!(rc, "# keep me\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn it_fails_if_the_shell_cannot_be_started() {
+    let dir = std::path::Path::new("target/tests/no-shell");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"no-shell\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\ngreet = \"echo hello && echo planet\"\n",
+    )
+    .unwrap();
+
+    in_dir("target/tests/no-shell")
+        .with_env(inherit().insert("PATH", "/nonexistent"))
+        .with_args(&["cmd", "greet"])
+        .fails_with(66)
+        .and()
+        .stderr()
+        .contains("error: Could not run \"sh\"")
+        .unwrap();
+}
+
+#[test]
+fn it_rejects_unknown_keys_in_strict_mode() {
+    fixture("strict")
+        .with_args(&["cmd", "build"])
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("building")
+        .unwrap();
+    fixture("strict")
+        .with_args(&["cmd", "--strict", "build"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Unknown key \"descripton\" in command \"build\" in Cargo.toml")
+        .unwrap();
+
+    let dir = std::path::Path::new("target/tests/strict-settings");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"strict-settings\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.cargo-cmd]\nfalback_to_cargo = true\n\n\
+         [package.metadata.cargo-cmd.services.dev]\nweb = { cmd = \"echo web\", restrat = \"always\" }\n\n\
+         [package.metadata.commands]\nbuild = \"echo building\"\n",
+    )
+    .unwrap();
+    in_dir("target/tests/strict-settings")
+        .with_args(&["cmd", "--strict", "build"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Unknown key \"falback_to_cargo\" in the settings in Cargo.toml:6")
+        .unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"strict-settings\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.cargo-cmd.services.dev]\nweb = { cmd = \"echo web\", restrat = \"always\" }\n\n\
+         [package.metadata.commands]\nbuild = \"echo building\"\n",
+    )
+    .unwrap();
+    in_dir("target/tests/strict-settings")
+        .with_args(&["cmd", "--strict", "build"])
+        .fails()
+        .and()
+        .stderr()
+        .contains("Unknown key \"restrat\" in command \"web\" in Cargo.toml:6")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_checks_for_a_newer_version() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new("target/tests/self-update");
+    std::fs::create_dir_all(dir).unwrap();
+    let cargo = dir.join("cargo");
+    std::fs::write(
+        &cargo,
+        "#!/bin/sh\necho 'cargo-cmd = \"99.0.0\"    # Alias any shell command'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&cargo, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    in_dir(".")
+        .with_args(&["cmd", "--self-update", "--check-only"])
+        .with_env(inherit().insert("CARGO", cargo.canonicalize().unwrap()))
+        .fails()
+        .and()
+        .stderr()
+        .contains("cargo-cmd 99.0.0 is available")
+        .unwrap();
+}
+
+#[test]
+fn it_reads_the_manifest_again_when_it_changes() {
+    let dir = std::path::Path::new("target/tests/cache");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    let manifest = "[package]\nname = \"cache\"\nversion = \"0.1.0\"\n\n\
+                    [package.metadata.commands]\n";
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!("{}hi = \"echo one\"\n", manifest),
+    )
+    .unwrap();
+
+    in_dir("target/tests/cache")
+        .with_args(&["cmd", "hi"])
+        .stdout()
+        .contains("one")
+        .unwrap();
+    assert!(dir.join("target/cargo-cmd/cache/commands.toml").exists());
+
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!("{}hi = \"echo two\"\n", manifest),
+    )
+    .unwrap();
+    in_dir("target/tests/cache")
+        .with_args(&["cmd", "hi"])
+        .stdout()
+        .contains("two")
+        .unwrap();
+    in_dir("target/tests/cache")
+        .with_args(&["cmd", "--no-cache", "hi"])
+        .stdout()
+        .contains("two")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_installs_missing_cargo_subcommands() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new("target/tests/auto-install");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    let bin = dir.join("bin").canonicalize().unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"auto-install\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.commands]\n\
+         lint = { cmd = \"cargo-fake-tool\", requires = [\"cargo-fake-tool\"] }\n",
+    )
+    .unwrap();
+    let cargo = dir.join("cargo");
+    std::fs::write(
+        &cargo,
+        format!(
+            "#!/bin/sh\nprintf '#!/bin/sh\\necho linted\\n' > {0}/$2\nchmod +x {0}/$2\n",
+            bin.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&cargo, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let env = inherit()
+        .insert("CARGO", cargo.canonicalize().unwrap())
+        .insert(
+            "PATH",
+            format!("{}:{}", bin.display(), std::env::var("PATH").unwrap()),
+        );
+
+    in_dir("target/tests/auto-install")
+        .with_args(&["cmd", "lint"])
+        .with_env(&env)
+        .fails()
+        .and()
+        .stderr()
+        .contains(
+            "cargo-fake-tool (not found, install it with `cargo install cargo-fake-tool --force`)",
+        )
+        .unwrap();
+    in_dir("target/tests/auto-install")
+        .with_args(&["cmd", "--auto-install", "lint"])
+        .with_env(&env)
+        .succeeds()
+        .and()
+        .stdout()
+        .contains("linted")
+        .unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn it_calls_plugins() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::path::Path::new("target/tests/plugins");
+    std::fs::remove_dir_all(dir).ok();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"plugins\"\nversion = \"0.1.0\"\n\n\
+         [package.metadata.cargo-cmd]\nplugins = [\"audit\"]\n\n\
+         [package.metadata.commands]\n\
+         hi = \"echo hi\"\ndrop = \"echo drop tables\"\n",
+    )
+    .unwrap();
+    let plugin = dir.join("cargo-cmd-audit");
+    std::fs::write(
+        &plugin,
+        "#!/bin/sh\ninput=$(cat)\necho \"$1 $input\" >> audit.log\n\
+         case \"$input\" in *drop*) [ \"$1\" != pre-spawn ];; esac\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let env = inherit().insert(
+        "PATH",
+        format!(
+            "{}:{}",
+            dir.canonicalize().unwrap().display(),
+            std::env::var("PATH").unwrap()
+        ),
+    );
+
+    in_dir("target/tests/plugins")
+        .with_args(&["cmd", "hi"])
+        .with_env(&env)
+        .succeeds()
+        .unwrap();
+    let log = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert!(log.contains(
+        r#"pre-resolution {"args":[],"command":"hi","event":"pre-resolution","package":"plugins"}"#
+    ));
+    assert!(log.contains(r#"pre-spawn {"cmd":"echo hi","command":"hi""#));
+    assert!(log.contains(r#"post-run {"args":[],"command":"hi""#));
+
+    in_dir("target/tests/plugins")
+        .with_args(&["cmd", "drop"])
+        .with_env(&env)
+        .fails()
+        .and()
+        .stderr()
+        .contains("error: Plugin \"audit\" failed on pre-spawn")
+        .and()
+        .stdout()
+        .doesnt_contain("drop tables")
+        .unwrap();
+
+    // Plugins can exit without reading the details.
+    std::fs::write(&plugin, "#!/bin/sh\nexit 0\n").unwrap();
+    for _ in 0..10 {
+        in_dir("target/tests/plugins")
+            .with_args(&["cmd", "hi"])
+            .with_env(&env)
+            .succeeds()
+            .unwrap();
+    }
+}